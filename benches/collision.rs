@@ -0,0 +1,59 @@
+// Times finding contacts by checking every pair against using the spatial hash.
+// Run with `cargo bench`.
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::time::{Duration, Instant};
+use unit2::collision::*;
+use unit2::types::Rect;
+
+const STEPS: u32 = 100;
+
+// A floor and side walls of 16x16 tiles, with particles scattered above
+fn scene(particles: usize) -> (Vec<Wall>, Vec<Mobile>) {
+    let mut walls = vec![];
+    for i in 0..100 {
+        walls.push(Wall::new(Rect{x: i * 16, y: 784, w: 16, h: 16}));
+    }
+    for i in 0..48 {
+        walls.push(Wall::new(Rect{x: 0, y: i * 16, w: 16, h: 16}));
+        walls.push(Wall::new(Rect{x: 1584, y: i * 16, w: 16, h: 16}));
+    }
+    let mut rng = StdRng::seed_from_u64(1);
+    let mobiles = (0..particles)
+        .map(|_| Mobile::new(Rect{x: rng.gen_range(0, 1600), y: rng.gen_range(0, 800), w: 8, h: 8}, 0, 0))
+        .collect();
+    (walls, mobiles)
+}
+
+fn time(mut f: impl FnMut() -> usize) -> (Duration, usize) {
+    let start = Instant::now();
+    let mut found = 0;
+    for _ in 0..STEPS {
+        found = f();
+    }
+    (start.elapsed() / STEPS, found)
+}
+
+fn main() {
+    for &particles in [100, 500, 2000].iter() {
+        let (walls, mobiles) = scene(particles);
+        let mut contacts = vec![];
+        let (brute, brute_found) = time(|| {
+            contacts.clear();
+            gather_contacts(&walls, &mobiles, &mut contacts);
+            contacts.len()
+        });
+        let mut hash = SpatialHash::new(16);
+        let (hashed, hashed_found) = time(|| {
+            contacts.clear();
+            hash.gather_contacts(&walls, &mobiles, &mut contacts);
+            contacts.len()
+        });
+        assert_eq!(brute_found, hashed_found);
+        println!(
+            "{} walls, {} particles, {} contacts: every pair {:?}, spatial hash {:?} ({:.1}x)",
+            walls.len(), particles, brute_found, brute, hashed,
+            brute.as_secs_f64() / hashed.as_secs_f64()
+        );
+    }
+}
@@ -1,63 +1,369 @@
-use crate::types::Rect;
-use std::time::{Duration, SystemTime};
+use crate::types::{Rect, Vec2i};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// One picture of an animation: where it is in the texture, and optionally which point
+/// of it goes at the sprite's position (the top left corner if there's no pivot).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Frame {
+    pub rect: Rect,
+    pub pivot: Option<Vec2i>,
+}
+
+impl Frame {
+    pub fn new(rect: Rect) -> Self {
+        Self { rect, pivot: None }
+    }
+    pub fn with_pivot(rect: Rect, pivot: Vec2i) -> Self {
+        Self { rect, pivot: Some(pivot) }
+    }
+    // How far up and left of the draw position the frame's corner goes
+    pub fn offset(&self) -> Vec2i {
+        self.pivot.unwrap_or(Vec2i(0, 0))
+    }
+}
+
+/// What happens when an animation gets past its last frame.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum LoopMode {
+    /// Play once and stay on the last frame
+    Once,
+    /// Play once and then stop being drawn
+    OnceHide,
+    /// Start over from the first frame, forever
+    Loop,
+    /// Go back and forth between the first and last frames, forever
+    PingPong,
+    /// Loop from the last frame to the first, forever
+    Reverse,
+    /// Play through this many times, then hold the last frame
+    Repeat(u16),
+}
 
 #[allow(dead_code)]
 pub struct Animation {
-
-    pub frame_width: u16,
-    pub frame_height: u16,
-    start_x: i32,
-    pub start_y: i32,
+    frames: Vec<Frame>,
     frame_count: u16,
-    pub current_frame: u16,
-    frame_duration: Duration,
-    last_frame_time: SystemTime,
-    active: bool,
-    pub do_loop: bool,
+    current_frame: u16,
+    // how long each frame stays up
+    frame_durations: Vec<Duration>,
+    // seconds spent on the current frame so far
+    elapsed: f64,
+    // 1.0 is normal speed, 0.5 half speed and so on
+    speed: f64,
+    paused: bool,
+    mode: LoopMode,
+    // +1 or -1, ping-pong turns around at the ends
+    direction: i32,
+    // full trips through the frames so far, for Repeat
+    plays: u16,
+    finished: bool,
+    // (frame, name) pairs, e.g. (3, "wing_down")
+    events: Vec<(u16, String)>,
+    // events from frames reached since the last take_events
+    fired: Vec<String>,
+    // the current frame's events haven't been fired yet (right after a reset)
+    fresh: bool,
 }
 
 #[allow(dead_code)]
 impl Animation {
 
+    // A row of equally sized frames going right from (start_x, start_y)
     pub fn new (frame_width: u16, frame_height: u16, start_x: i32, start_y: i32, frame_count: u16)-> Self {
+        Self::grid(frame_width, frame_height, start_x, start_y, frame_count, frame_count)
+    }
+    /// Equally sized frames read left to right, `columns` to a row, then top to bottom.
+    pub fn grid(frame_width: u16, frame_height: u16, start_x: i32, start_y: i32, columns: u16, frame_count: u16) -> Self {
+        assert!(columns > 0, "A grid needs at least one column");
+        let frames = (0..frame_count)
+            .map(|i| Frame::new(Rect {
+                x: start_x + (i % columns) as i32 * frame_width as i32,
+                y: start_y + (i / columns) as i32 * frame_height as i32,
+                w: frame_width,
+                h: frame_height,
+            }))
+            .collect();
+        Self::from_frames(frames)
+    }
+    pub fn from_frames(frames: Vec<Frame>) -> Self {
+        assert!(!frames.is_empty(), "An animation needs at least one frame");
+        let frame_count = frames.len() as u16;
         Self {
-            frame_width: frame_width,
-            frame_height: frame_height,
-            start_x: start_x,
-            start_y: start_y,
-            frame_count: frame_count,
+            frames,
+            frame_count,
             current_frame: 0,
-            frame_duration: Duration::from_millis(500),
-            last_frame_time: SystemTime::now(),
-            active: false,
-            do_loop: true,
+            frame_durations: vec![Duration::from_millis(500); frame_count as usize],
+            elapsed: 0.0,
+            speed: 1.0,
+            paused: false,
+            mode: LoopMode::Loop,
+            direction: 1,
+            plays: 0,
+            finished: false,
+            events: vec![],
+            fired: vec![],
+            fresh: true,
         }
     }
+    // Give every frame the same duration
     pub fn set_duration(&mut self, duration: Duration) {
-        self.frame_duration = duration;
+        self.frame_durations = vec![duration; self.frame_count as usize];
+    }
+    pub fn set_frame_durations(&mut self, durations: Vec<Duration>) {
+        assert_eq!(durations.len(), self.frame_count as usize, "Need one duration per frame");
+        self.frame_durations = durations;
+    }
+    pub fn set_frame_duration(&mut self, frame: u16, duration: Duration) {
+        self.frame_durations[frame as usize] = duration;
+    }
+    // Also restarts the animation, since e.g. Reverse starts somewhere else
+    pub fn set_mode(&mut self, mode: LoopMode) {
+        self.mode = mode;
+        self.reset();
+    }
+    /// Fire an event named `name` every time the animation reaches `frame`.
+    pub fn add_event(&mut self, frame: u16, name: &str) {
+        assert!(frame < self.frame_count, "Frame {} is past the end of the animation", frame);
+        self.events.push((frame, name.to_string()));
+    }
+    /// Names of the events from frames reached since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.fired)
+    }
+    pub fn mode(&self) -> LoopMode {
+        self.mode
+    }
+    pub fn frame_count(&self) -> u16 {
+        self.frame_count
+    }
+    /// Has a non-looping animation played all the way through?
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+    /// OnceHide animations disappear once they finish.
+    pub fn visible(&self) -> bool {
+        !(self.finished && self.mode == LoopMode::OnceHide)
+    }
+    pub fn set_speed(&mut self, speed: f64) {
+        assert!(speed >= 0.0, "Animations can't run backwards");
+        self.speed = speed;
+    }
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    // Back to the start, ready to play again
+    pub fn reset(&mut self) {
+        self.current_frame = if self.mode == LoopMode::Reverse { self.frame_count - 1 } else { 0 };
+        self.elapsed = 0.0;
+        self.direction = if self.mode == LoopMode::Reverse { -1 } else { 1 };
+        self.plays = 0;
+        self.finished = false;
+        self.fresh = true;
     }
 
+    // Jump straight to `frame`, e.g. to hold a pose until the animation is reset; its events fire
+    // once it plays on from there
+    pub fn seek(&mut self, frame: u16) {
+        self.current_frame = frame.min(self.frame_count - 1);
+        self.elapsed = 0.0;
+        self.finished = false;
+        self.fresh = true;
+    }
+    pub fn current_frame(&self) -> u16 {
+        self.current_frame
+    }
+    pub fn frame(&self) -> &Frame {
+        &self.frames[self.current_frame as usize]
+    }
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+    // Give every frame the same pivot
+    pub fn set_pivot(&mut self, pivot: Vec2i) {
+        for frame in self.frames.iter_mut() {
+            frame.pivot = Some(pivot);
+        }
+    }
+    // The current frame's rectangle in the texture
     pub fn calc_frame(&self) -> Rect {
-        let x_pos = self.start_x + (self.current_frame as i32) * (self.frame_width as i32);
+        self.frame().rect
+    }
 
-        return Rect{x: x_pos, y: self.start_y, w: self.frame_width, h: self.frame_height};
+    // Call this from the game's update with the simulation step (e.g. DT), not from drawing code
+    pub fn advance(&mut self, dt: f64) {
+        if self.paused || self.finished {
+            return;
+        }
+        self.enter_first_frame();
+        // All-zero durations would never use up any time
+        if self.frame_durations.iter().all(|d| *d == Duration::from_secs(0)) {
+            return;
+        }
+        self.elapsed += dt * self.speed;
+        loop {
+            let frame_duration = self.frame_durations[self.current_frame as usize].as_secs_f64();
+            if self.finished || self.elapsed < frame_duration {
+                break;
+            }
+            self.elapsed -= frame_duration;
+            self.next_frame();
+        }
+    }
+    // Step a whole number of frames, ignoring frame durations and speed
+    pub fn advance_frames(&mut self, frames: u16) {
+        if self.paused {
+            return;
+        }
+        self.enter_first_frame();
+        for _ in 0..frames {
+            self.next_frame();
+        }
+    }
+
+    // The frame we start on counts as reached once the animation actually starts playing
+    fn enter_first_frame(&mut self) {
+        if self.fresh {
+            self.fresh = false;
+            self.fire(self.current_frame);
+        }
+    }
+    fn fire(&mut self, frame: u16) {
+        for (f, name) in self.events.iter() {
+            if *f == frame {
+                self.fired.push(name.clone());
+            }
+        }
     }
 
-    pub fn tick (&mut self) {
-        let now = SystemTime::now();
-        let time_elapsed = now.duration_since(self.last_frame_time);
-        match time_elapsed {
-            Ok(duration) => {if duration.as_millis() > self.frame_duration.as_millis() {
-                self.last_frame_time = now;
-                if self.do_loop {
-                    self.current_frame = (self.current_frame + 1) % self.frame_count;
+    fn next_frame(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.step_frame();
+        // Holding the last frame after finishing doesn't count as reaching it again
+        if !self.finished {
+            self.fire(self.current_frame);
+        }
+    }
+    fn step_frame(&mut self) {
+        let last = self.frame_count - 1;
+        match self.mode {
+            LoopMode::Loop => self.current_frame = (self.current_frame + 1) % self.frame_count,
+            LoopMode::Reverse => self.current_frame = (self.current_frame + last) % self.frame_count,
+            LoopMode::Once | LoopMode::OnceHide => {
+                if self.current_frame < last {
+                    self.current_frame += 1;
+                } else {
+                    self.finished = true;
+                }
+            }
+            LoopMode::Repeat(times) => {
+                if self.current_frame < last {
+                    self.current_frame += 1;
                 } else {
-                    if self.current_frame < self.frame_count - 1 {
-                        self.current_frame += 1;
+                    self.plays += 1;
+                    if self.plays >= times {
+                        self.finished = true;
+                    } else {
+                        self.current_frame = 0;
                     }
                 }
-                }},
-            Err(_) => {println!("oops");},
+            }
+            LoopMode::PingPong => {
+                if last == 0 {
+                    return;
+                }
+                if (self.direction > 0 && self.current_frame == last) || (self.direction < 0 && self.current_frame == 0) {
+                    self.direction = -self.direction;
+                }
+                self.current_frame = (self.current_frame as i32 + self.direction) as u16;
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Named frames in a texture, loaded from a text file with a line per frame:
+/// `name x y w h` and optionally the pivot `px py` after that. `#` starts a comment.
+pub struct Atlas {
+    frames: HashMap<String, Frame>,
+}
+
+#[allow(dead_code)]
+impl Atlas {
+    pub fn new() -> Self {
+        Self { frames: HashMap::new() }
+    }
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut atlas = Self::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.len() != 5 && words.len() != 7 {
+                return Err(format!("line {}: expected name x y w h and maybe px py", number + 1));
+            }
+            let n = |i: usize| words[i].parse::<i32>().map_err(|_| format!("line {}: {} isn't a number", number + 1, words[i]));
+            let size = |i: usize| words[i].parse::<u16>().map_err(|_| format!("line {}: {} isn't a width or height", number + 1, words[i]));
+            let rect = Rect { x: n(1)?, y: n(2)?, w: size(3)?, h: size(4)? };
+            let frame = if words.len() == 7 { Frame::with_pivot(rect, Vec2i(n(5)?, n(6)?)) } else { Frame::new(rect) };
+            atlas.insert(words[0], frame);
+        }
+        Ok(atlas)
+    }
+    pub fn insert(&mut self, name: &str, frame: Frame) {
+        self.frames.insert(name.to_string(), frame);
+    }
+    pub fn get(&self, name: &str) -> Option<Frame> {
+        self.frames.get(name).copied()
+    }
+    /// An animation of the named frames, in the given order. None if any of them is missing.
+    pub fn animation(&self, names: &[&str]) -> Option<Animation> {
+        let frames = names.iter().map(|name| self.get(name)).collect::<Option<Vec<_>>>()?;
+        if frames.is_empty() {
+            return None;
+        }
+        Some(Animation::from_frames(frames))
+    }
+    /// Every frame named `prefix` and then a number, like "bird_0" up to "bird_12", in number order.
+    pub fn sequence(&self, prefix: &str) -> Option<Animation> {
+        let mut numbered: Vec<(u64, &str)> = self
+            .frames
+            .keys()
+            .filter_map(|name| {
+                let digits = name.strip_prefix(prefix)?;
+                // parse would also take a leading +
+                if !digits.bytes().all(|b| b.is_ascii_digit()) {
+                    return None;
+                }
+                Some((digits.parse().ok()?, name.as_str()))
+            })
+            .collect();
+        numbered.sort();
+        let names: Vec<&str> = numbered.into_iter().map(|(_, name)| name).collect();
+        self.animation(&names)
+    }
+}
+
+impl Default for Atlas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
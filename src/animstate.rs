@@ -0,0 +1,160 @@
+use std::collections::{HashMap, HashSet};
+
+/// Something a transition can wait for. Parameters the game never set count as 0/false.
+#[derive(Clone, Debug)]
+pub enum Condition {
+    Above(String, f64),
+    Below(String, f64),
+    Flag(String, bool),
+    /// Fires once per `trigger` call
+    Trigger(String),
+}
+
+/// A way out of one state (or out of any state) into another.
+#[derive(Clone, Debug)]
+pub struct Transition {
+    // None means any state
+    from: Option<String>,
+    to: String,
+    conditions: Vec<Condition>,
+    wait_for_clip: bool,
+}
+
+#[allow(dead_code)]
+impl Transition {
+    pub fn new(from: &str, to: &str) -> Self {
+        Self {
+            from: Some(from.to_string()),
+            to: to.to_string(),
+            conditions: vec![],
+            wait_for_clip: false,
+        }
+    }
+    // Taken from whatever state we're in, except `to` itself
+    pub fn from_any(to: &str) -> Self {
+        Self {
+            from: None,
+            ..Self::new("", to)
+        }
+    }
+    // All conditions have to hold at once
+    pub fn when(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+    // Don't leave until the current clip has finished; only useful for clips that don't loop
+    pub fn after_clip_end(mut self) -> Self {
+        self.wait_for_clip = true;
+        self
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum StateEvent {
+    Enter(String),
+    Exit(String),
+}
+
+/// Named animation states (idle, flap, dive...) for a sprite, each playing one of
+/// its animations, with transitions between them driven by parameters the game sets.
+pub struct StateMachine {
+    // state name -> index into the sprite's animations, in the order they were added
+    states: Vec<(String, usize)>,
+    transitions: Vec<Transition>,
+    params: HashMap<String, f64>,
+    triggers: HashSet<String>,
+    current: Option<usize>,
+    events: Vec<StateEvent>,
+}
+
+#[allow(dead_code)]
+impl StateMachine {
+    pub fn new() -> Self {
+        Self {
+            states: vec![],
+            transitions: vec![],
+            params: HashMap::new(),
+            triggers: HashSet::new(),
+            current: None,
+            events: vec![],
+        }
+    }
+    /// The first state added is the one the sprite starts in.
+    pub fn add_state(&mut self, name: &str, animation: usize) {
+        assert!(self.find(name).is_none(), "State {} already exists", name);
+        self.states.push((name.to_string(), animation));
+    }
+    // Transitions are checked in the order they were added and the first one that fits is taken
+    pub fn add_transition(&mut self, transition: Transition) {
+        if let Some(from) = &transition.from {
+            assert!(self.find(from).is_some(), "No state called {}", from);
+        }
+        assert!(self.find(&transition.to).is_some(), "No state called {}", transition.to);
+        self.transitions.push(transition);
+    }
+    pub fn set_param(&mut self, name: &str, value: f64) {
+        self.params.insert(name.to_string(), value);
+    }
+    pub fn set_flag(&mut self, name: &str, value: bool) {
+        self.set_param(name, if value { 1.0 } else { 0.0 });
+    }
+    // Good for the next update only, whether or not a transition used it
+    pub fn trigger(&mut self, name: &str) {
+        self.triggers.insert(name.to_string());
+    }
+    pub fn current(&self) -> Option<&str> {
+        self.current.map(|i| self.states[i].0.as_str())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+    /// Enter and exit events since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<StateEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Pick the next state. Returns the animation to switch to if the state changed.
+    /// `clip_finished` is whether the current state's animation has played through.
+    pub fn update(&mut self, clip_finished: bool) -> Option<usize> {
+        let next = match self.current {
+            None if !self.states.is_empty() => Some(0),
+            None => None,
+            Some(current) => self.transitions.iter()
+                .filter(|t| t.from.as_ref().map_or(true, |from| *from == self.states[current].0))
+                .filter(|t| t.to != self.states[current].0)
+                .filter(|t| !t.wait_for_clip || clip_finished)
+                .find(|t| t.conditions.iter().all(|c| self.holds(c)))
+                .map(|t| self.find(&t.to).unwrap()),
+        };
+        // A trigger only counts for the update right after it was set
+        self.triggers.clear();
+        let next = next?;
+        if let Some(current) = self.current {
+            self.events.push(StateEvent::Exit(self.states[current].0.clone()));
+        }
+        self.events.push(StateEvent::Enter(self.states[next].0.clone()));
+        self.current = Some(next);
+        Some(self.states[next].1)
+    }
+
+    fn find(&self, name: &str) -> Option<usize> {
+        self.states.iter().position(|(n, _)| n == name)
+    }
+    fn param(&self, name: &str) -> f64 {
+        self.params.get(name).copied().unwrap_or(0.0)
+    }
+    fn holds(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Above(name, value) => self.param(name) > *value,
+            Condition::Below(name, value) => self.param(name) < *value,
+            Condition::Flag(name, value) => (self.param(name) != 0.0) == *value,
+            Condition::Trigger(name) => self.triggers.contains(name),
+        }
+    }
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
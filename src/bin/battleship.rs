@@ -1,3 +1,5 @@
+// Savefile's derive puts its impls inside a const, which newer compilers warn about
+#![allow(non_local_definitions)]
 use pixels::{Pixels, SurfaceTexture};
 use std::path::Path;
 use std::rc::Rc;
@@ -18,10 +20,14 @@ extern crate savefile_derive;
 
 use unit2::screen::Screen;
 use unit2::texture::Texture;
+use unit2::texture::PixelFormat;
 use unit2::types::*;
+use unit2::query::overlap_point_tiles;
 use unit2::tiles::*;
 use unit2::sound::*;
+use unit2::hotreload::HotReload;
 use unit2::texture::stack_horizontal;
+use unit2::timeline::{Cue, Timeline};
 
 
 //GameState - saved and loaded from file
@@ -37,6 +43,13 @@ struct GameState {
 struct GameData {
     sound: Sound,
     font: fontdue::Font,
+    assets: HotReload,
+    // plays instead of the current mode while it's there
+    cutscene: Option<Timeline>,
+    // shared by every tilemap, including ones that were just loaded
+    tileset: Rc<Tileset>,
+    // the one being watched, which the loaded game state gets pointed at
+    title_image: Rc<Texture>,
 }
 // seconds per frame
 const DT: f64 = 1.0 / 60.0;
@@ -45,6 +58,7 @@ const MAPDIM: i32 = 64;
 const WIDTH: usize = 16*12; //192
 const HEIGHT: usize = 16*16; //256
 const DEPTH: usize = 4;
+const TEXT_COL: Rgba = Rgba(255, 255, 255, 255);
 
 ///////////Mac: 
 //const XMAX: usize = 384;
@@ -113,8 +127,9 @@ impl Mode {
                             Mode::ScoreBoard
                         }else if input.key_pressed(VirtualKeyCode::R) {
                             Mode::Reset
-                        }else if input.mouse_pressed(0) {
-                            
+                        }else if input.mouse_pressed(0) && input.mouse().is_some_and(|m| overlap_point_tiles(&game.tilemaps[0], m, |_| true).is_some()) {
+                            // only clicks on the opponent's grid count
+
 
                             let xcoor = input.mouse().unwrap().0 as i32;
                             let ycoor = input.mouse().unwrap().1 as i32;
@@ -122,16 +137,22 @@ impl Mode {
                             //change tile at coordinates
                             //was opponent's ship hidden there?
                             if game.tilemaps[0].tile_at(Vec2i(xcoor, ycoor)).opphit {
-                                data.sound.play_sound("hit".to_string());
-                                game.compsunk = game.compsunk + 1;
+                                game.compsunk += 1;
                                 game.tilemaps[0].set_tile_at(Vec2i(xcoor, ycoor), 8); //hit opponent
+                                let cell = overlap_point_tiles(&game.tilemaps[0], (xcoor as f32, ycoor as f32), |_| true).unwrap();
+                                if sunk(&game.tilemaps[0], cell) {
+                                    // the cutscene plays the hit sound
+                                    data.cutscene = load_cutscene("./res/sink_battleship.txt");
+                                } else {
+                                    data.sound.play_sound("hit".to_string());
+                                }
                             } else { //missed
                                 data.sound.play_sound("splash".to_string());
                                 game.tilemaps[0].set_tile_at(Vec2i(xcoor, ycoor), 12); //missed opponent
                             }
 
-                            save_game(&game);
-                            let reloaded_game = load_game();
+                            save_game(game);
+                            let reloaded_game = load_game(data);
                             *game = reloaded_game; 
 
                             Mode::Play(Turn::Computer)
@@ -152,7 +173,7 @@ impl Mode {
                         //hits human's ship
                         if game.tilemaps[1].tile_at(Vec2i(xcompguess, ycompguess)).myship {
                             data.sound.play_sound("hit".to_string());
-                            game.humansunk = game.humansunk + 1;
+                            game.humansunk += 1;
                             game.tilemaps[1].set_tile_at(Vec2i(xcompguess, ycompguess), 4); //hit human's ship
                             Mode::Play(Turn::Human)
 
@@ -236,7 +257,7 @@ impl Mode {
                 game.tilemaps = vec![oppmap, mymap];
                 game.compsunk = 0;
                 game.humansunk = 0;
-                save_game(&game);
+                save_game(game);
 
                 Mode::Play(Turn::Human)
             }
@@ -279,7 +300,7 @@ impl Mode {
                 game.tilemaps = vec![oppmap, mymap];
                 game.compsunk = 0;
                 game.humansunk = 0;
-                save_game(&game);
+                save_game(game);
                 
                 if input.key_pressed(VirtualKeyCode::Q) {
                     panic!();
@@ -331,7 +352,7 @@ impl Mode {
                 game.tilemaps = vec![oppmap, mymap];
                 game.compsunk = 0;
                 game.humansunk = 0;
-                save_game(&game);
+                save_game(game);
 
                 if input.key_pressed(VirtualKeyCode::Q) {
                     panic!();
@@ -381,42 +402,42 @@ impl Mode {
                 screen.clear(Rgba(0, 0, 0, 255));
 
                 let options_tex = create_text_tex(&data.font, "OPTIONS".to_string());
-                let from_rect_options = Rect{x: 0, y: 0, w: options_tex.width as u16, h: options_tex.height as u16};
-                let to_pos_options = Vec2i((WIDTH - options_tex.width) as i32 / 2, (HEIGHT - options_tex.height) as i32 / 6);
-                screen.bitblt(&options_tex, from_rect_options, to_pos_options);
+                let from_rect_options = Rect{x: 0, y: 0, w: options_tex.width() as u16, h: options_tex.height() as u16};
+                let to_pos_options = Vec2i((WIDTH - options_tex.width()) as i32 / 2, (HEIGHT - options_tex.height()) as i32 / 6);
+                screen.bitblt_mask(&options_tex, from_rect_options, to_pos_options, TEXT_COL);
 
                 let score_tex = create_text_tex(&data.font, "S>>>Score".to_string());
-                let from_rect_score = Rect{x: 0, y: 0, w: score_tex.width as u16, h: score_tex.height as u16};
-                let to_pos_score = Vec2i((WIDTH - score_tex.width) as i32 / 2, (HEIGHT - score_tex.height) as i32 / 3);
-                screen.bitblt(&score_tex, from_rect_score, to_pos_score);
+                let from_rect_score = Rect{x: 0, y: 0, w: score_tex.width() as u16, h: score_tex.height() as u16};
+                let to_pos_score = Vec2i((WIDTH - score_tex.width()) as i32 / 2, (HEIGHT - score_tex.height()) as i32 / 3);
+                screen.bitblt_mask(&score_tex, from_rect_score, to_pos_score, TEXT_COL);
 
                 let quit_tex = create_text_tex(&data.font, "Q>>>Quit".to_string());
-                let from_rect_quit = Rect{x: 0, y: 0, w: quit_tex.width as u16, h: quit_tex.height as u16};
-                let to_pos_quit = Vec2i((WIDTH - quit_tex.width) as i32 / 2, (HEIGHT - quit_tex.height) as i32 / 2);
-                screen.bitblt(&quit_tex, from_rect_quit, to_pos_quit);
+                let from_rect_quit = Rect{x: 0, y: 0, w: quit_tex.width() as u16, h: quit_tex.height() as u16};
+                let to_pos_quit = Vec2i((WIDTH - quit_tex.width()) as i32 / 2, (HEIGHT - quit_tex.height()) as i32 / 2);
+                screen.bitblt_mask(&quit_tex, from_rect_quit, to_pos_quit, TEXT_COL);
 
                 let play_tex = create_text_tex(&data.font, "P>>>Play".to_string());
-                let from_rect_play = Rect{x: 0, y: 0, w: play_tex.width as u16, h: play_tex.height as u16};
-                let to_pos_play = Vec2i((WIDTH - play_tex.width) as i32 / 2, (HEIGHT - play_tex.height) as i32 / 3 * 2);
-                screen.bitblt(&play_tex, from_rect_play, to_pos_play);
+                let from_rect_play = Rect{x: 0, y: 0, w: play_tex.width() as u16, h: play_tex.height() as u16};
+                let to_pos_play = Vec2i((WIDTH - play_tex.width()) as i32 / 2, (HEIGHT - play_tex.height()) as i32 / 3 * 2);
+                screen.bitblt_mask(&play_tex, from_rect_play, to_pos_play, TEXT_COL);
             }
             Mode::ScoreBoard => {
                 screen.clear(Rgba(0, 0, 0, 255));
 
                 let highscore_tex = create_text_tex(&data.font, "TALLY".to_string());
-                let from_rect = Rect{x: 0, y: 0, w: highscore_tex.width as u16, h: highscore_tex.height as u16};
-                let to_pos = Vec2i((WIDTH - highscore_tex.width) as i32 / 2, (HEIGHT - highscore_tex.height) as i32 / 4);
-                screen.bitblt(&highscore_tex, from_rect, to_pos);
+                let from_rect = Rect{x: 0, y: 0, w: highscore_tex.width() as u16, h: highscore_tex.height() as u16};
+                let to_pos = Vec2i((WIDTH - highscore_tex.width()) as i32 / 2, (HEIGHT - highscore_tex.height()) as i32 / 4);
+                screen.bitblt_mask(&highscore_tex, from_rect, to_pos, TEXT_COL);
 
                 let comp_score_tex = create_text_tex(&data.font, "Computer:    ".to_string() + &game.humansunk.to_string());
-                let comp_from_rect = Rect{x: 0, y: 0, w: comp_score_tex.width as u16, h: comp_score_tex.height as u16};
-                let comp_to_pos = Vec2i((WIDTH - comp_score_tex.width) as i32 / 2, (HEIGHT - comp_score_tex.height) as i32 / 2);
-                screen.bitblt(&comp_score_tex, comp_from_rect, comp_to_pos);
+                let comp_from_rect = Rect{x: 0, y: 0, w: comp_score_tex.width() as u16, h: comp_score_tex.height() as u16};
+                let comp_to_pos = Vec2i((WIDTH - comp_score_tex.width()) as i32 / 2, (HEIGHT - comp_score_tex.height()) as i32 / 2);
+                screen.bitblt_mask(&comp_score_tex, comp_from_rect, comp_to_pos, TEXT_COL);
 
                 let hum_score_tex = create_text_tex(&data.font, "You:    ".to_string() + &game.compsunk.to_string());
-                let hum_from_rect = Rect{x: 0, y: 0, w: hum_score_tex.width as u16, h: hum_score_tex.height as u16};
-                let hum_to_pos = Vec2i((WIDTH - hum_score_tex.width) as i32 / 2, (HEIGHT - hum_score_tex.height) as i32 / 4 * 3);
-                screen.bitblt(&hum_score_tex, hum_from_rect, hum_to_pos);
+                let hum_from_rect = Rect{x: 0, y: 0, w: hum_score_tex.width() as u16, h: hum_score_tex.height() as u16};
+                let hum_to_pos = Vec2i((WIDTH - hum_score_tex.width()) as i32 / 2, (HEIGHT - hum_score_tex.height()) as i32 / 4 * 3);
+                screen.bitblt_mask(&hum_score_tex, hum_from_rect, hum_to_pos, TEXT_COL);
             }
             Mode::Reset => {
                 screen.clear(Rgba(0, 0, 0, 255));
@@ -427,27 +448,54 @@ impl Mode {
             Mode::WonGame => { 
                 screen.clear(Rgba(0, 0, 0, 255));
                 let tex = create_text_tex(&data.font, "WINNER!".to_string());
-                let from_rect = Rect{x: 0, y: 0, w: tex.width as u16, h: tex.height as u16};
-                let to_pos = Vec2i((WIDTH - tex.width) as i32 / 2, (HEIGHT - tex.height) as i32 / 2);
-                screen.bitblt(&tex, from_rect, to_pos);
+                let from_rect = Rect{x: 0, y: 0, w: tex.width() as u16, h: tex.height() as u16};
+                let to_pos = Vec2i((WIDTH - tex.width()) as i32 / 2, (HEIGHT - tex.height()) as i32 / 2);
+                screen.bitblt_mask(&tex, from_rect, to_pos, TEXT_COL);
             }
             Mode::LostGame => { 
                 screen.clear(Rgba(0, 0, 0, 255));
                 let tex = create_text_tex(&data.font, "GAME OVER!".to_string());
-                let from_rect = Rect{x: 0, y: 0, w: tex.width as u16, h: tex.height as u16};
-                let to_pos = Vec2i((WIDTH - tex.width) as i32 / 2, (HEIGHT - tex.height) as i32 / 2);
-                screen.bitblt(&tex, from_rect, to_pos);
+                let from_rect = Rect{x: 0, y: 0, w: tex.width() as u16, h: tex.height() as u16};
+                let to_pos = Vec2i((WIDTH - tex.width()) as i32 / 2, (HEIGHT - tex.height()) as i32 / 2);
+                screen.bitblt_mask(&tex, from_rect, to_pos, TEXT_COL);
             }
         }
     }
 }
 
+// Cells (column, row) of each of the opponent's ships, as Mode::Reset lays them out
+const OPP_SHIPS: [&[(i32, i32)]; 4] = [
+    &[(1, 0), (2, 0)],                 // double ship
+    &[(8, 3), (9, 3), (8, 4), (9, 4)], // pirate ship
+    &[(2, 5), (2, 6)],                 // tall ship
+    &[(9, 7)],                         // single ship
+];
+
+// Whether the ship at `cell` has no hidden parts left
+fn sunk(map: &Tilemap, cell: (i32, i32)) -> bool {
+    let ship = match OPP_SHIPS.iter().find(|ship| ship.contains(&cell)) {
+        Some(ship) => ship,
+        None => return false,
+    };
+    ship.iter().all(|(col, row)| {
+        let at = Vec2i(map.position.0 + col * SZ as i32, map.position.1 + row * SZ as i32);
+        !map.tile_at(at).opphit
+    })
+}
+
 fn save_game(game:&GameState) {
-    save_file("save_battleship.bin", 0, game).unwrap();
+    save_file("save_battleship.bin", 4, game).unwrap();
 }
 
-fn load_game() -> GameState {
-    load_file("save_battleship.bin", 0).unwrap()
+// Saves refer to textures by path; point the game at the watched ones instead,
+// or hot reload stops once the loaded copies replace them
+fn load_game(data: &GameData) -> GameState {
+    let mut game: GameState = load_file("save_battleship.bin", 4).unwrap();
+    game.title_image = Rc::clone(&data.title_image);
+    for tilemap in game.tilemaps.iter_mut() {
+        tilemap.tileset = Rc::clone(&data.tileset);
+    }
+    game
 } 
 
 fn main() {
@@ -477,11 +525,10 @@ fn main() {
     game_sound.add_sound("splash".to_string(), "./res/splash.mp3".to_string());
 
     //font
-    let font:&[u8];
     //Mac
-    font = include_bytes!("../../res/Exo2-Regular.ttf");
+    let font:&[u8] = include_bytes!("../../res/Exo2-Regular.ttf");
     //Windows
-    //font = include_bytes!("..\\..\\res\\Exo2-Regular.ttf") as &[u8];
+    //let font:&[u8] = include_bytes!("..\\..\\res\\Exo2-Regular.ttf");
 
     
     let settings = fontdue::FontSettings {
@@ -490,102 +537,123 @@ fn main() {
     };
     let font = fontdue::Font::from_bytes(font, settings).unwrap();
 
-    let mut data = GameData {sound: game_sound, font: font};
-
     let title_image = Rc::new(Texture::with_file(Path::new("./res/logo.png")));
 
+    // Pick up edits to anything in res/ while the game runs
+    let mut assets = HotReload::new();
+    assets.watch_dir(Path::new("./res"));
+    assets.watch_texture(&title_image);
+    let tileset_tex = Rc::new(Texture::with_file(Path::new("./res/tileset.png")));
+    assets.watch_texture(&tileset_tex);
+
     //create Tileset from tileset.png image
-    let _boattileset = Rc::new(Tileset {
+    let boattileset = Rc::new(Tileset {
         tiles: vec![
             //spritesheet comprises 16 tiles
             Tile {
                 oppgrid: true,
                 opphit: false,
                 myship: false,
+                collision: TileCollision::Empty,
             }, //empty opponent - 0
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: false,
+                collision: TileCollision::Empty,
             }, //ocean - 1
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: false,
+                collision: TileCollision::Empty,
             }, //ocean - 2
             Tile {
                 oppgrid: true,
                 opphit: true,
                 myship: false,
+                collision: TileCollision::Empty,
             }, //hidden opponent - 3
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: false,
+                collision: TileCollision::Empty,
             }, //my ship hit - 4
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: true,
+                collision: TileCollision::Empty,
             }, //single ship - 5
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: true,
+                collision: TileCollision::Empty,
             }, //double ship 1 - 6
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: true,
+                collision: TileCollision::Empty,
             }, //double ship 2 - 7
             Tile {
                 oppgrid: true,
                 opphit: false,
                 myship: false,
+                collision: TileCollision::Empty,
             }, //hit opponent - 8
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: true,
+                collision: TileCollision::Empty,
             }, //tall ship 1 - 9
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: true,
+                collision: TileCollision::Empty,
             }, //pirate ship 1 - 10
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: true,
+                collision: TileCollision::Empty,
             }, //pirate ship 2 - 11
             Tile {
                 oppgrid: true,
                 opphit: false,
                 myship: false,
+                collision: TileCollision::Empty,
             }, //missed opponent - 12
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: true,
+                collision: TileCollision::Empty,
             }, //tall ship 2 - 13
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: true,
+                collision: TileCollision::Empty,
             }, //pirate ship 3 - 14
             Tile {
                 oppgrid: false,
                 opphit: false,
                 myship: true,
+                collision: TileCollision::Empty,
             }, //pirate ship 4 - 15
         ],
-        texture: Rc::new(Texture::with_file(Path::new("./res/tileset.png"))), //bring in image as texture
+        texture: tileset_tex, //bring in image as texture
     });
+    let mut data = GameData {sound: game_sound, font, assets, cutscene: None, tileset: Rc::clone(&boattileset), title_image};
 
     let mut mode = Mode::Title;
     //load saved GameState
-    let mut state = load_game();
-    state.title_image = title_image;
+    let mut state = load_game(&data);
 
     // How many unsimulated frames have we saved up?
     let mut available_time = 0.0;
@@ -600,6 +668,13 @@ fn main() {
             screen.clear(Rgba(0, 0, 0, 0));
 
             mode.display(&state, &mut data, &mut screen);
+            if let Some(cutscene) = data.cutscene.as_ref() {
+                for (text, position) in cutscene.texts() {
+                    let text_tex = create_text_tex(&data.font, text.to_string());
+                    let from_rect = Rect{x: 0, y: 0, w: text_tex.width() as u16, h: text_tex.height() as u16};
+                    screen.bitblt_mask(&text_tex, from_rect, position, TEXT_COL);
+                }
+            }
 
             // Flip buffers
             if pixels.render().is_err() {
@@ -623,11 +698,21 @@ fn main() {
                 pixels.resize(size.width, size.height);
             }
         }
+        // Hot reloading is only for development
+        if cfg!(debug_assertions) {
+            for path in data.assets.poll() {
+                data.sound.reload_path(&path);
+            }
+        }
         // And the simulation "consumes" it
         while available_time >= DT {
             // Eat up one frame worth of time
             available_time -= DT;
-            mode = mode.update(&mut state, &mut data, &input);
+            mode = if data.cutscene.is_some() {
+                play_cutscene(mode, &mut state, &mut data, &input)
+            } else {
+                mode.update(&mut state, &mut data, &input)
+            };
         }
         // Request redraw
         window.request_redraw();
@@ -637,6 +722,48 @@ fn main() {
 }
 
 
+fn load_cutscene(path: &str) -> Option<Timeline> {
+    match Timeline::load(Path::new(path)) {
+        Ok(timeline) => Some(timeline),
+        Err(e) => {
+            println!("couldn't load cutscene: {}", e);
+            None
+        }
+    }
+}
+
+// Runs the cutscene for a step instead of the mode, which it can switch by name
+fn play_cutscene(mode: Mode, game: &mut GameState, data: &mut GameData, input: &WinitInputHelper) -> Mode {
+    let cutscene = data.cutscene.as_mut().unwrap();
+    let mut mode = mode;
+    for cue in cutscene.update(DT, input.mouse_pressed(0)) {
+        match cue {
+            Cue::Move { target, position } if target == "oppmap" => game.tilemaps[0].position = position,
+            Cue::Move { target, position } if target == "mymap" => game.tilemaps[1].position = position,
+            Cue::Sound(name) => data.sound.play_sound(name),
+            Cue::Mode(name) => {
+                mode = match name.as_str() {
+                    "title" => Mode::Title,
+                    "play" => Mode::Play(Turn::Human),
+                    "computer" => Mode::Play(Turn::Computer),
+                    "scoreboard" => Mode::ScoreBoard,
+                    "won" => Mode::WonGame,
+                    "lost" => Mode::LostGame,
+                    _ => {
+                        println!("cutscene asked for unknown mode {}", name);
+                        mode
+                    }
+                }
+            }
+            cue => println!("cutscene can't do {:?}", cue),
+        }
+    }
+    if cutscene.finished() {
+        data.cutscene = None;
+    }
+    mode
+}
+
 fn create_text_tex(font: &fontdue::Font, text: String) -> Rc<Texture> {
     let font_size = 30.0;
     let mut char_textures: Vec<Texture> = vec![];
@@ -644,11 +771,11 @@ fn create_text_tex(font: &fontdue::Font, text: String) -> Rc<Texture> {
     while i < text.len() {
         let character = text.chars().nth(i).unwrap();
         let (metrics, bitmap) = font.rasterize(character, font_size);
-        let mut char_tex = Texture::from_vec(bitmap, metrics.width, metrics.height, 1);
-        char_tex.convert_to_rgba();
+        // Glyphs stay greyscale coverage; bitblt_mask colours them in
+        let char_tex = Texture::with_format(bitmap, metrics.width, metrics.height, PixelFormat::L8);
         char_textures.push(char_tex);
         i += 1;
     }
-    return Rc::new(stack_horizontal(char_textures));
+    Rc::new(stack_horizontal(char_textures))
 }
 
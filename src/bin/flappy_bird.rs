@@ -16,14 +16,21 @@ use rand::{thread_rng, Rng};
 
 use unit2::screen::Screen;
 use unit2::texture::Texture;
+use unit2::texture::PixelFormat;
 use unit2::texture::stack_horizontal;
-use unit2::animation::Animation;
+use unit2::resample::Filter;
+use unit2::animation::{Animation, Frame, LoopMode};
+use unit2::animstate::{Condition, Transition};
+use unit2::timeline::{Cue, Timeline};
+use unit2::tween::{Curve, Ease, Tween, Tweening};
 use unit2::sprite::*;
 use unit2::types::*;
 use unit2::collision::*;
-use unit2::collision::rect_touching;
 use unit2::entity::*;
+use unit2::scene::Node;
+use unit2::skeleton::Skeleton;
 use unit2::sound::Sound;
+use unit2::hotreload::{canonical, HotReload};
 use unit2::tiles::*;
 use unit2::tiles::TILE_SZ;
 extern crate savefile;
@@ -41,6 +48,8 @@ const HEIGHT: usize = 500;
 
 
 const CLEAR_COL: Rgba = Rgba(0, 0, 0, 0);
+const TEXT_COL: Rgba = Rgba(255, 255, 255, 255);
+const SCORE_OUTLINE: Rgba = Rgba(255, 255, 100, 255);
 
 
 const OBSTACLE_SPACING: u16 = 250;
@@ -49,11 +58,20 @@ const GAP_HEIGHT: usize = 175;
 const OBSTACLE_MIN_HEIGHT: u16 = 50;
 const OBSTACLE_MAX_HEIGHT: u16 = (HEIGHT - GAP_HEIGHT) as u16 - OBSTACLE_MIN_HEIGHT;
 const OBSTACLE_SPEED: u16 = 4;
-const MIN_OBSTACLES: usize = (WIDTH / (OBSTACLE_SPACING + OBSTACLE_WIDTH) as usize) * 2 + 1;
+const MIN_PIPES: usize = WIDTH / (OBSTACLE_SPACING + OBSTACLE_WIDTH) as usize + 1;
 const BACKGROUND_SPEED: u16 = 1;
+// in pixels per frame (per frame)
+const GRAVITY: Fixed = Fixed::from_frac(3, 8);
+const FLAP_VELOCITY: Fixed = Fixed::from_frac(-9, 2);
+const MAX_FALL: Fixed = Fixed::from_int(5);
 const MAP_WIDTH: usize = WIDTH / TILE_SZ + 1;
 const MAP_HEIGHT: usize = HEIGHT / TILE_SZ + 1;
 const MAP_SIZE: usize = MAP_WIDTH * MAP_HEIGHT;
+// collision layers
+const BIRD: u32 = 1;
+const BOUNDS: u32 = 2;
+const PIPE: u32 = 4;
+const GAP: u32 = 8;
 
 #[derive(Debug, Copy, Clone)]
 enum Mode {
@@ -65,15 +83,34 @@ enum Mode {
 }
 
 
+// A pair of pipes and the gap between them. Touching a pipe is the end, flying through the gap scores.
+struct Pipes {
+    bottom: Wall,
+    top: Wall,
+    gap: Wall,
+    bottom_sprite: Sprite,
+    top_sprite: Sprite,
+}
+
+impl Pipes {
+    fn scroll(&mut self, dx: i32) {
+        for wall in [&mut self.bottom, &mut self.top, &mut self.gap].iter_mut() {
+            wall.rect.translate(dx, 0);
+        }
+    }
+}
+
 struct GameState {
-    player: Bird,
-    obstacles: Vec<Entity>,
-    accel_down: i32,
+    player: Entity,
+    pipes: Vec<Pipes>,
     finished: bool,
     score: usize,
     score_tex: Rc<Texture>,
+    // the score box outline flashes white when a pipe is passed
+    score_flash: Tween<Rgba>,
     tilemaps: Vec<Tilemap>,
     walls: Vec<Wall>,
+    collisions: CollisionWorld,
 }
 
 //#[derive(Savefile)]
@@ -83,20 +120,41 @@ struct GameData {
     title_tex: Rc<Texture>,
     player_tex: Rc<Texture>,
     wing_tex: Rc<Texture>,
+    // twice the size for the title screen, made from the ones above
+    player_tex_big: Rc<Texture>,
+    wing_tex_big: Rc<Texture>,
+    bird_bones: Skeleton,
     font: fontdue::Font,
     sound: Sound,
     sky_tex: Rc<Texture>,
     highscore: usize,
     sound_on: bool,
+    assets: HotReload,
+    title_slide: Tween<Vec2i>,
+    // scripted bird on the title screen
+    intro: Timeline,
+    intro_bird: Entity,
 }
 
 impl Mode {
     // update consumes self and yields a new state (which might also just be self)
     fn update(self, state: &mut GameState, data: &mut GameData, input: &WinitInputHelper) -> Self {
+        // the title slides in again every time we come back to it
+        if let Mode::Title = self {
+            data.title_slide.advance(DT);
+        } else {
+            data.title_slide.reset();
+            data.intro.reset();
+        }
         match self {
             Mode::Title => {
-
-                if input.key_pressed(VirtualKeyCode::P) {
+                // a bad intro gets dropped like one that didn't load
+                let intro_mode = play_intro(data, input).unwrap_or_else(|e| {
+                    println!("couldn't play the intro: {}", e);
+                    data.intro = Timeline::new();
+                    None
+                });
+                if input.key_pressed(VirtualKeyCode::P) || intro_mode.as_deref() == Some("play") {
                     *state = new_game(data);
                     Mode::Play(false)
                 }
@@ -226,11 +284,17 @@ impl Mode {
                     w: 250,
                     h: 51,
                 };
-                screen.bitblt(&data.title_tex, display_rect, Vec2i(275, 224));
+                screen.bitblt(&data.title_tex, display_rect, data.title_slide.value());
+                screen.draw_entity(&data.intro_bird);
+                for (text, position) in data.intro.texts() {
+                    let text_tex = create_text_tex(&data.font, text.to_string());
+                    let from_rect_text = Rect{x: 0, y: 0, w: text_tex.width() as u16, h: text_tex.height() as u16};
+                    screen.bitblt_mask(&text_tex, from_rect_text, position, TEXT_COL);
+                }
                 let play_tex = create_text_tex(&data.font, "P>>>Play".to_string());
-                let from_rect_play = Rect{x: 0, y: 0, w: play_tex.width as u16, h: play_tex.height as u16};
-                let to_pos_play = Vec2i((WIDTH - play_tex.width) as i32 / 2, (HEIGHT - play_tex.height) as i32 / 3 * 2);
-                screen.bitblt(&play_tex, from_rect_play, to_pos_play);
+                let from_rect_play = Rect{x: 0, y: 0, w: play_tex.width() as u16, h: play_tex.height() as u16};
+                let to_pos_play = Vec2i((WIDTH - play_tex.width()) as i32 / 2, (HEIGHT - play_tex.height()) as i32 / 3 * 2);
+                screen.bitblt_mask(&play_tex, from_rect_play, to_pos_play, TEXT_COL);
             }
             Mode::Play(_paused) => {
                 // Call screen's drawing methods to render the game state
@@ -243,40 +307,40 @@ impl Mode {
                 screen.clear(Rgba(0, 0, 0, 255));
                 
                 let options_tex = create_text_tex(&data.font, "OPTIONS".to_string());
-                let from_rect_options = Rect{x: 0, y: 0, w: options_tex.width as u16, h: options_tex.height as u16};
-                let to_pos_options = Vec2i((WIDTH - options_tex.width) as i32 / 2, (HEIGHT - options_tex.height) as i32 / 6);
-                screen.bitblt(&options_tex, from_rect_options, to_pos_options);
+                let from_rect_options = Rect{x: 0, y: 0, w: options_tex.width() as u16, h: options_tex.height() as u16};
+                let to_pos_options = Vec2i((WIDTH - options_tex.width()) as i32 / 2, (HEIGHT - options_tex.height()) as i32 / 6);
+                screen.bitblt_mask(&options_tex, from_rect_options, to_pos_options, TEXT_COL);
 
                 let score_tex = create_text_tex(&data.font, "S>>>Highscore".to_string());
-                let from_rect_score = Rect{x: 0, y: 0, w: score_tex.width as u16, h: score_tex.height as u16};
-                let to_pos_score = Vec2i((WIDTH - score_tex.width) as i32 / 2, (HEIGHT - score_tex.height) as i32 / 3);
-                screen.bitblt(&score_tex, from_rect_score, to_pos_score);
+                let from_rect_score = Rect{x: 0, y: 0, w: score_tex.width() as u16, h: score_tex.height() as u16};
+                let to_pos_score = Vec2i((WIDTH - score_tex.width()) as i32 / 2, (HEIGHT - score_tex.height()) as i32 / 3);
+                screen.bitblt_mask(&score_tex, from_rect_score, to_pos_score, TEXT_COL);
 
                 let quit_tex = create_text_tex(&data.font, "Q>>>Quit".to_string());
-                let from_rect_quit = Rect{x: 0, y: 0, w: quit_tex.width as u16, h: quit_tex.height as u16};
-                let to_pos_quit = Vec2i((WIDTH - quit_tex.width) as i32 / 2, (HEIGHT - quit_tex.height) as i32 / 2);
-                screen.bitblt(&quit_tex, from_rect_quit, to_pos_quit);
+                let from_rect_quit = Rect{x: 0, y: 0, w: quit_tex.width() as u16, h: quit_tex.height() as u16};
+                let to_pos_quit = Vec2i((WIDTH - quit_tex.width()) as i32 / 2, (HEIGHT - quit_tex.height()) as i32 / 2);
+                screen.bitblt_mask(&quit_tex, from_rect_quit, to_pos_quit, TEXT_COL);
 
                 let play_tex = create_text_tex(&data.font, "P>>>Play".to_string());
-                let from_rect_play = Rect{x: 0, y: 0, w: play_tex.width as u16, h: play_tex.height as u16};
-                let to_pos_play = Vec2i((WIDTH - play_tex.width) as i32 / 2, (HEIGHT - play_tex.height) as i32 / 3 * 2);
-                screen.bitblt(&play_tex, from_rect_play, to_pos_play);
+                let from_rect_play = Rect{x: 0, y: 0, w: play_tex.width() as u16, h: play_tex.height() as u16};
+                let to_pos_play = Vec2i((WIDTH - play_tex.width()) as i32 / 2, (HEIGHT - play_tex.height()) as i32 / 3 * 2);
+                screen.bitblt_mask(&play_tex, from_rect_play, to_pos_play, TEXT_COL);
 
                 let mut owned_string = "D>>>Sound: ".to_owned();
                 let borrowed_string = data.sound_on.to_string();
                 owned_string.push_str(&borrowed_string);
                 let sound_tex = create_text_tex(&data.font, owned_string);
-                let from_rect_sound = Rect{x: 0, y: 0, w: sound_tex.width as u16, h: sound_tex.height as u16};
-                let to_pos_sound = Vec2i((WIDTH - sound_tex.width) as i32 / 2, (HEIGHT - sound_tex.height) as i32 / 6 * 5);
-                screen.bitblt(&sound_tex, from_rect_sound, to_pos_sound);
+                let from_rect_sound = Rect{x: 0, y: 0, w: sound_tex.width() as u16, h: sound_tex.height() as u16};
+                let to_pos_sound = Vec2i((WIDTH - sound_tex.width()) as i32 / 2, (HEIGHT - sound_tex.height()) as i32 / 6 * 5);
+                screen.bitblt_mask(&sound_tex, from_rect_sound, to_pos_sound, TEXT_COL);
             }
             Mode::ScoreBoard => {
                 screen.clear(Rgba(0, 0, 0, 255));
                 let highscore_tex = create_text_tex(&data.font, "Highscore:    ".to_string() + &data.highscore.to_string());
                 // todo: change stack_horizontal to fill space rather than take shortest character
-                let from_rect = Rect{x: 0, y: 0, w: highscore_tex.width as u16, h: highscore_tex.height as u16};
-                let to_pos = Vec2i((WIDTH - highscore_tex.width) as i32 / 2, (HEIGHT - highscore_tex.height) as i32 / 2);
-                screen.bitblt(&highscore_tex, from_rect, to_pos);
+                let from_rect = Rect{x: 0, y: 0, w: highscore_tex.width() as u16, h: highscore_tex.height() as u16};
+                let to_pos = Vec2i((WIDTH - highscore_tex.width()) as i32 / 2, (HEIGHT - highscore_tex.height()) as i32 / 2);
+                screen.bitblt_mask(&highscore_tex, from_rect, to_pos, TEXT_COL);
             }
             Mode::EndGame => { // Draw game result?
                 screen.clear(Rgba(255, 255, 80, 255));
@@ -320,6 +384,15 @@ fn main() {
     let title_tex = Rc::new(Texture::with_file(Path::new("./res/TitleImage.png")));
     let wing_tex = Rc::new(Texture::with_file(Path::new("./res/wings.png")));
     let sky_tex = Rc::new(Texture::with_file(Path::new("./res/flappy_sky_dilute.png")));
+    let player_tex_big = Rc::new(player_tex.scale(2.0, Filter::Epx));
+    let wing_tex_big = Rc::new(wing_tex.scale(2.0, Filter::Epx));
+
+    // Pick up edits to anything in res/ while the game runs
+    let mut assets = HotReload::new();
+    assets.watch_dir(Path::new("./res"));
+    for tex in [&player_tex, &obstacle_tex_up, &obstacle_tex_down, &title_tex, &wing_tex, &sky_tex].iter() {
+        assets.watch_texture(tex);
+    }
 
     let mut game_sound = Sound::new();
     let _ = game_sound.init_manager();
@@ -329,12 +402,10 @@ fn main() {
     game_sound.add_sound("die".to_string(), "./res/die.mp3".to_string());
 
     let mut mode = Mode::Title;
-    let font:&[u8];// = include_bytes!("..\\res\\Exo2-Regular.ttf") as &[u8];
-    
     ///////Mac
-    font = include_bytes!("../../res/Exo2-Regular.ttf");
+    let font:&[u8] = include_bytes!("../../res/Exo2-Regular.ttf");
     ///////Windows
-    //font = include_bytes!("..\\..\\res\\Exo2-Regular.ttf") as &[u8];
+    //let font:&[u8] = include_bytes!("..\\..\\res\\Exo2-Regular.ttf");
 
     let settings = fontdue::FontSettings {
         scale: 12.0,
@@ -344,18 +415,35 @@ fn main() {
 
     let (highscore, sound_on) = load_data();
 
+    let intro = Timeline::load(Path::new("./res/intro_flappy.txt")).unwrap_or_else(|e| {
+        println!("couldn't load the intro: {}", e);
+        Timeline::new()
+    });
+    let bird_bones = Skeleton::load(Path::new("./res/bird_bones.txt")).unwrap_or_else(|e| {
+        println!("couldn't load the bird's bones: {}", e);
+        Skeleton::new()
+    });
+    let intro_bird = new_bird(&player_tex_big, &wing_tex_big, &bird_bones, 2);
+
 
     let mut data = GameData {
-        obstacle_tex_up: obstacle_tex_up,
-        obstacle_tex_down: obstacle_tex_down,
-        title_tex: title_tex,
-        player_tex: player_tex,
-        font: font,
-        wing_tex: wing_tex,
+        obstacle_tex_up,
+        obstacle_tex_down,
+        title_tex,
+        player_tex,
+        font,
+        wing_tex,
+        player_tex_big,
+        wing_tex_big,
+        bird_bones,
         sound: game_sound,
-        sky_tex: sky_tex,
-        highscore: highscore,
-        sound_on: sound_on,
+        sky_tex,
+        highscore,
+        sound_on,
+        assets,
+        title_slide: Tween::new(Vec2i(275, -51), Vec2i(275, 224), Duration::from_millis(1200)).with_ease(Ease::Out(Curve::Bounce)),
+        intro,
+        intro_bird,
     };
 
     let mut state = new_game(&data);
@@ -401,6 +489,13 @@ fn main() {
                 pixels.resize(size.width, size.height);
             }
         }
+        // Hot reloading is only for development
+        if cfg!(debug_assertions) {
+            for path in data.assets.poll() {
+                data.sound.reload_path(&path);
+                reload_data(&path, &mut state, &mut data);
+            }
+        }
         // And the simulation "consumes" it
         while available_time >= DT {
             // Eat up one frame worth of time
@@ -413,7 +508,7 @@ fn main() {
     });
 }
 
-fn draw_game(state: &mut GameState, screen: &mut Screen) {
+fn draw_game(state: &GameState, screen: &mut Screen) {
     // Call screen's drawing methods to render the game state
     screen.clear(Rgba(80, 80, 80, 255));
 
@@ -421,162 +516,132 @@ fn draw_game(state: &mut GameState, screen: &mut Screen) {
         tilemap.draw(screen);
     }
 
-    for obs in state.obstacles.iter_mut() {
-        screen.draw_entity(obs);
+    for pipes in state.pipes.iter() {
+        screen.draw_sprite_at(&pipes.bottom_sprite, Vec2i(pipes.bottom.rect.x, pipes.bottom.rect.y));
+        screen.draw_sprite_at(&pipes.top_sprite, Vec2i(pipes.top.rect.x, pipes.top.rect.y));
     }
     
     //draw score
     let score_rect = Rect{x: (WIDTH / 2 - 70) as i32, y: 0, w: 160, h: 30};
     screen.rect(score_rect, Rgba(0, 0, 0,255));
-    screen.rect_outline(score_rect, Rgba(255, 255, 100, 255));
-    let score_text_rect = Rect{x: 0, y: 0, w: state.score_tex.width as u16, h: state.score_tex.height as u16};
+    screen.rect_outline(score_rect, state.score_flash.value());
+    let score_text_rect = Rect{x: 0, y: 0, w: state.score_tex.width() as u16, h: state.score_tex.height() as u16};
     let score_text_pos = Vec2i((WIDTH / 2) as i32, 10);
     
-    screen.bitblt(&state.score_tex, score_text_rect, score_text_pos);
+    screen.bitblt_mask(&state.score_tex, score_text_rect, score_text_pos, TEXT_COL);
     
     
-    state.player.body.sprite.animations[0].current_frame = scale_range(state.player.body.hitbox.vy, -10.0, 7.0, 0.0, 4.0) as u16;
-    screen.draw_bird(&mut state.player);
+    screen.draw_entity(&state.player);
 
     
 }
 
 
 fn update_game(state: &mut GameState, input: &WinitInputHelper, data: &mut GameData) {
-    let player = &mut state.player.body.hitbox;
+    if input.key_pressed(VirtualKeyCode::Up) {
+        // the jump sound waits for the wing to come down, see below
+        flap(&mut state.player);
+    }
+    let player = &mut state.player.hitbox;
     // Determine player velocity
-    if input.key_held(VirtualKeyCode::Left) {
-
-    } else if input.key_held(VirtualKeyCode::Right) {
-
-    } else if input.key_pressed(VirtualKeyCode::Down) {
-
-    } else {
-        player.vx = 0;
+    if !(input.key_held(VirtualKeyCode::Left) || input.key_held(VirtualKeyCode::Right) || input.key_pressed(VirtualKeyCode::Down)) {
+        player.vx = Fixed::ZERO;
     }
-    let mut accel_down = state.accel_down;
+    // a flap sets the bird going up, then gravity pulls it a bit more each frame
     if input.key_pressed(VirtualKeyCode::Up) {
-        accel_down = -4;
-        //accel_down = -2;
-        if data.sound_on {
-            data.sound.play_sound("jump".to_string());
-        }
-        
-        state.player.wing.animations[0].current_frame = 0;
+        player.vy = FLAP_VELOCITY;
     } else {
-        accel_down += 1;
-        if accel_down > 1 {
-            accel_down = 1;
-        }
+        player.vy += GRAVITY;
     }
-    state.accel_down = accel_down;
-    player.vy += accel_down;
-    //clamp velocity since this restitution assumes objects aren't speeding too much
-
-    let min_velocity = -5;
-    let max_velocity = 5;
-    if player.vy > max_velocity {
-        player.vy = max_velocity;
-    }
-    if player.vy < min_velocity {
-        player.vy = min_velocity;
-    }
-
-    if state.obstacles.len() < MIN_OBSTACLES {
-        if state.obstacles.len() == 0 || WIDTH as i32 - state.obstacles[state.obstacles.len() - 1].hitbox.rect.x - (OBSTACLE_WIDTH as i32) >= OBSTACLE_SPACING as i32 {
-            let new_height = thread_rng().gen_range(OBSTACLE_MIN_HEIGHT, OBSTACLE_MAX_HEIGHT);
-            // pipe_up; bottom pipe
-            {let new_hitbox = Mobile {
-                rect: Rect {
-                    x: WIDTH as i32 - OBSTACLE_WIDTH as i32,
-                    y: HEIGHT as i32 - new_height as i32,
-                    w: OBSTACLE_WIDTH,
-                    h: new_height as u16,
-                },
-                vx: OBSTACLE_SPEED as i32 * -1,
-                vy: 0,
-            };
-            let mut new_sprite = Sprite::new(
-                &data.obstacle_tex_up,
-                Rect {
-                    x: 0,
-                    y: 0,
-                    w: 30,
-                    h: 400,
-                },
-                Vec2i(0, 0),
-            );
-            
-            let new_animation = Animation::new(OBSTACLE_WIDTH, new_height as u16, 0, 0, 1);
-            new_sprite.animations.push(new_animation);
-            let new_obstacle = Entity::new(new_hitbox, new_sprite, false);
-            state.obstacles.push(new_obstacle);}
-            // pipe_down, top pipe
-            {
-            let new_height_2 = HEIGHT - new_height as usize - GAP_HEIGHT;
-            let new_hitbox = Mobile {
-                rect: Rect {
-                    x: WIDTH as i32 - OBSTACLE_WIDTH as i32,
-                    y: 0,
-                    w: OBSTACLE_WIDTH,
-                    h: new_height_2 as u16,
-                },
-                vx: OBSTACLE_SPEED as i32 * -1,
-                vy: 0,
-            };
-            let mut new_sprite = Sprite::new(
-                &data.obstacle_tex_down,
-                Rect {
-                    x: 0,
-                    y: 0,
-                    w: 30,
-                    h: 400,
-                },
-                Vec2i(0, 0),
-            );
-            let new_animation = Animation::new(OBSTACLE_WIDTH, new_height_2 as u16, 0, 400 - new_height_2 as i32, 1);
-            new_sprite.animations.push(new_animation);
-            let new_obstacle = Entity::new(new_hitbox, new_sprite, false);
-            state.obstacles.push(new_obstacle);}
-        }
+    //clamp velocity so the bird stays controllable
+    player.vy = player.vy.clamp(-MAX_FALL, MAX_FALL);
+
+    if state.pipes.len() < MIN_PIPES
+        && (state.pipes.is_empty() || WIDTH as i32 - state.pipes[state.pipes.len() - 1].bottom.rect.x - (OBSTACLE_WIDTH as i32) >= OBSTACLE_SPACING as i32)
+    {
+        let new_height = thread_rng().gen_range(OBSTACLE_MIN_HEIGHT, OBSTACLE_MAX_HEIGHT);
+        let new_height_2 = (HEIGHT - new_height as usize - GAP_HEIGHT) as u16;
+        let x = WIDTH as i32 - OBSTACLE_WIDTH as i32;
+        // pipe_up; bottom pipe
+        let bottom_frame = Rect{x: 0, y: 0, w: OBSTACLE_WIDTH, h: new_height};
+        let mut bottom_sprite = Sprite::new(&data.obstacle_tex_up, Vec2i(0, 0));
+        bottom_sprite.animations.push(Animation::from_frames(vec![Frame::new(bottom_frame)]));
+        // pipe_down, top pipe
+        let top_frame = Rect{x: 0, y: 400 - new_height_2 as i32, w: OBSTACLE_WIDTH, h: new_height_2};
+        let mut top_sprite = Sprite::new(&data.obstacle_tex_down, Vec2i(0, 0));
+        top_sprite.animations.push(Animation::from_frames(vec![Frame::new(top_frame)]));
+        // the pipes are a bit narrower than their rims, so there's see-through space along their sides
+        let bottom_pixels = PixelMask::new(Rc::new(Mask::from_texture(&data.obstacle_tex_up, bottom_frame)), Vec2i(0, 0));
+        let top_pixels = PixelMask::new(Rc::new(Mask::from_texture(&data.obstacle_tex_down, top_frame)), Vec2i(0, 0));
+        state.pipes.push(Pipes {
+            bottom: Wall::new(Rect{x, y: HEIGHT as i32 - new_height as i32, w: OBSTACLE_WIDTH, h: new_height})
+                .with_layers(PIPE, BIRD).as_trigger().with_pixels(bottom_pixels),
+            top: Wall::new(Rect{x, y: 0, w: OBSTACLE_WIDTH, h: new_height_2})
+                .with_layers(PIPE, BIRD).as_trigger().with_pixels(top_pixels),
+            gap: Wall::new(Rect{x, y: new_height_2 as i32, w: OBSTACLE_WIDTH, h: GAP_HEIGHT as u16})
+                .with_layers(GAP, BIRD).as_trigger(),
+            bottom_sprite,
+            top_sprite,
+        });
     }
 
     // check front pipe to see if it needs to be deleted
-    if state.obstacles[0].hitbox.rect.x < 0 - OBSTACLE_WIDTH as i32 {
-            let _ = state.obstacles.remove(0);
-            let _2 = state.obstacles.remove(0);
-            state.score += 1;
-            if data.sound_on {
-                data.sound.play_sound("pass".to_string());
-            }
-            
-            state.score_tex = create_score_tex(&data.font, state.score);
+    if state.pipes[0].bottom.rect.x < 0 - OBSTACLE_WIDTH as i32 {
+        state.pipes.remove(0);
+        // its three walls come right after state.walls; the next pipe's take their place
+        let first = state.walls.len();
+        state.collisions.remove_statics(first..first + 3);
     }
-    player.update();
-    
-    // collisions
-    for wall in state.walls.iter() {
-        if rect_touching(wall.rect, player.rect) {
-            state.finished = true;
-            if data.sound_on {
-                data.sound.play_sound("die".to_string());
+    // collisions: the world moves the bird, so it can't skip over the thin floor and ceiling.
+    // Hitting those ends the game, and so does getting into a pipe
+    let mut statics: Vec<Wall> = state.walls.clone();
+    for pipes in state.pipes.iter() {
+        statics.extend_from_slice(&[pipes.bottom.clone(), pipes.top.clone(), pipes.gap.clone()]);
+    }
+    let mut died = !state.collisions.advance(&statics, std::slice::from_mut(player)).is_empty();
+    for event in state.collisions.trigger_events() {
+        let layer = match event.b {
+            ColliderID::Static(i) => statics[i].layer,
+            ColliderID::Dynamic(_) => continue,
+        };
+        match (layer, event.overlap) {
+            (PIPE, Overlap::Enter) => died = true,
+            // all the way through the gap
+            (GAP, Overlap::Exit) if !state.finished => {
+                state.score += 1;
+                if data.sound_on {
+                    data.sound.play_sound("pass".to_string());
+                }
+                state.score_tex = create_score_tex(&data.font, state.score);
+                state.score_flash = Tween::new(Rgba(255, 255, 255, 255), SCORE_OUTLINE, Duration::from_millis(400)).with_ease(Ease::Out(Curve::Quad));
             }
-            break;
+            _ => {}
         }
     }
-
-    for obs in state.obstacles.iter() {
-        if rect_touching(obs.hitbox.rect, player.rect) {
-            state.finished = true;
-            if data.sound_on {
-                data.sound.play_sound("die".to_string());
-            }
-            break;
+    if died && !state.finished {
+        state.finished = true;
+        if data.sound_on {
+            data.sound.play_sound("die".to_string());
         }
     }
 
-    for obs in state.obstacles.iter_mut() {
-        obs.hitbox.update();
+    for pipes in state.pipes.iter_mut() {
+        pipes.scroll(-(OBSTACLE_SPEED as i32));
+    }
+
+    // the body's state machine picks a frame from the bird's speed, the wing flaps on its own
+    let body = &mut state.player;
+    body.sprite.states.set_param("vy", body.hitbox.vy.to_f32() as f64);
+    if state.finished {
+        body.sprite.states.trigger("die");
+    }
+    state.player.advance(DT);
+    settle(&mut state.player);
+    state.score_flash.advance(DT);
+    for event in state.player.take_events() {
+        if event == "wing_down" && data.sound_on {
+            data.sound.play_sound("jump".to_string());
+        }
     }
 
     for tm in state.tilemaps.iter_mut() {
@@ -592,59 +657,153 @@ fn update_game(state: &mut GameState, input: &WinitInputHelper, data: &mut GameD
     }
 }
 
-fn new_game(data: &GameData) -> GameState {
+// The mode the intro asks for, if it does; errors are cues the bird can't follow
+fn play_intro(data: &mut GameData, input: &WinitInputHelper) -> Result<Option<String>, String> {
+    let mut mode = None;
+    for cue in data.intro.update(DT, input.key_pressed(VirtualKeyCode::Up)) {
+        match cue {
+            Cue::Move { target, position } if target == "bird" => {
+                data.intro_bird.hitbox.set_position((Fixed::from_int(position.0), Fixed::from_int(position.1)));
+            }
+            Cue::Animate { target, animation } if target == "wing" => {
+                if let Some(wing) = data.intro_bird.sprite_mut("wing") {
+                    if animation >= wing.animations.len() {
+                        return Err(format!("the wing has no animation {}", animation));
+                    }
+                    wing.set_animation(animation);
+                }
+                flap(&mut data.intro_bird);
+            }
+            Cue::Sound(name) => {
+                if data.sound_on {
+                    data.sound.play_sound(name);
+                }
+            }
+            Cue::Mode(name) => mode = Some(name),
+            cue => return Err(format!("the intro can't do {:?}", cue)),
+        }
+    }
+    data.intro_bird.advance(DT);
+    settle(&mut data.intro_bird);
+    // no flap sounds on the title screen
+    data.intro_bird.take_events();
+    Ok(mode)
+}
+
+// The intro and the bones are plain data rather than shared assets, and the big bird is made from the small one, so they're redone here when their files change
+fn reload_data(path: &Path, state: &mut GameState, data: &mut GameData) {
+    if path == canonical(Path::new("./res/intro_flappy.txt")) {
+        match Timeline::load(path) {
+            Ok(intro) => data.intro = intro,
+            Err(e) => println!("couldn't reload the intro: {}", e),
+        }
+    } else if path == canonical(Path::new("./res/bird.png")) {
+        // the small one has already been reloaded
+        data.player_tex_big.replace(data.player_tex.scale(2.0, Filter::Epx));
+    } else if path == canonical(Path::new("./res/wings.png")) {
+        data.wing_tex_big.replace(data.wing_tex.scale(2.0, Filter::Epx));
+    } else if path == canonical(Path::new("./res/bird_bones.txt")) {
+        match Skeleton::load(path) {
+            Ok(bones) => {
+                // the birds keep playing what they were playing, with the new keys
+                for bird in [&mut state.player, &mut data.intro_bird] {
+                    let playing = bird.skeleton.as_ref().and_then(|s| s.playing().map(|name| name.to_string()));
+                    let mut skeleton = bones.clone();
+                    if let Some(name) = playing {
+                        skeleton.play(&name);
+                    }
+                    bird.skeleton = Some(skeleton);
+                }
+                data.bird_bones = bones;
+            }
+            Err(e) => println!("couldn't reload the bird's bones: {}", e),
+        }
+    }
+}
 
-    let mut player_sprite = Sprite::new(
-        &data.player_tex,
-        Rect {
-            x: 0,
-            y: 128,
-            w: 64,
-            h: 64,
-        },
-        Vec2i(0, 0),
-    );
-    let mut player_animation = Animation::new(32, 32, 0, 0, 5);
-    player_animation.set_duration(Duration::new(3600, 0));
-    player_sprite.animations.push(player_animation);
-    let player_hitbox = Mobile{rect: Rect{x:32, y:45, w: 25, h: 25}, vx:0, vy: 0};
-    let body = Entity::new(player_hitbox, player_sprite, true);
-    let mut wing = Sprite::new(
-        &data.wing_tex,
-        Rect {
-            x: 0,
-            y: 128,
-            w: 64,
-            h: 64,
-        },
-        Vec2i(0, 0),
-    );
-    let mut wing_animation = Animation::new(22, 48, 0, 0, 9);
+// `size` is how many times bigger than bird.png and wings.png the textures are
+fn new_bird(player_tex: &Rc<Texture>, wing_tex: &Rc<Texture>, bones: &Skeleton, size: i32) -> Entity {
+    let px = |n: u16| n * size as u16;
+    let mut player_sprite = Sprite::new(player_tex, Vec2i(0, 0));
+    // centered on the hitbox
+    player_sprite.anchor = (0.5, 0.5);
+    // one still frame per state: idle is level, flap points up, dive points down
+    for (i, (name, frame)) in [("idle", 2), ("flap", 1), ("dive", 3), ("dead", 4)].iter().enumerate() {
+        player_sprite.animations.push(Animation::new(px(32), px(32), frame * 32 * size, 0, 1));
+        player_sprite.states.add_state(name, i);
+    }
+    let states = &mut player_sprite.states;
+    states.add_transition(Transition::from_any("dead").when(Condition::Trigger("die".to_string())));
+    states.add_transition(Transition::new("idle", "flap").when(Condition::Below("vy".to_string(), -1.5)));
+    states.add_transition(Transition::new("idle", "dive").when(Condition::Above("vy".to_string(), 2.5)));
+    states.add_transition(Transition::new("flap", "dive").when(Condition::Above("vy".to_string(), 2.5)));
+    states.add_transition(Transition::new("flap", "idle").when(Condition::Above("vy".to_string(), -1.5)));
+    states.add_transition(Transition::new("dive", "flap").when(Condition::Below("vy".to_string(), -1.5)));
+    states.add_transition(Transition::new("dive", "idle").when(Condition::Below("vy".to_string(), 2.5)));
+    // the whole sprite, the pixel masks sort out what actually gets hit
+    let player_hitbox = Mobile::new(Rect{x:29, y:42, w: px(32), h: px(32)}, 0, 0).with_layers(BIRD, BOUNDS | PIPE | GAP);
+    let mut body = Entity::new(player_hitbox, player_sprite, true).with_pixel_masks();
+    let mut wing = Sprite::new(wing_tex, Vec2i(0, 0));
+    let mut wing_animation = Animation::new(px(22), px(48), 0, 0, 9);
     wing_animation.set_duration(Duration::from_millis(30));
-    wing_animation.do_loop = false;
+    wing_animation.set_mode(LoopMode::Once);
+    // this spot on the wing goes at the middle of the body
+    wing_animation.set_pivot(Vec2i(16 * size, 28 * size));
+    wing_animation.add_event(3, "wing_down");
+    // rest on the last frame until the first flap
+    wing_animation.seek(8);
     wing.animations.push(wing_animation);
-    let player = Bird{body: body, wing: wing};
+    body.add_child(Node::with_sprite("wing", wing));
+    let mut bones = bones.clone();
+    bones.play("bob");
+    body.skeleton = Some(bones);
+    body
+}
+
+// Start the wing's flap from the top, swinging it as it goes
+fn flap(bird: &mut Entity) {
+    if let Some(wing) = bird.sprite_mut("wing") {
+        let current = wing.current_animation;
+        wing.animations[current].reset();
+    }
+    if let Some(bones) = bird.skeleton.as_mut() {
+        bones.play("flap");
+    }
+}
+
+// Call after advancing: back to gliding once a flap is over
+fn settle(bird: &mut Entity) {
+    if let Some(bones) = bird.skeleton.as_mut() {
+        if bones.finished() {
+            bones.play("bob");
+        }
+    }
+}
+
+fn new_game(data: &GameData) -> GameState {
+    let player = new_bird(&data.player_tex, &data.wing_tex, &data.bird_bones, 1);
     
-    let obstacles: Vec<Entity> = vec![];
+    let pipes: Vec<Pipes> = vec![];
     let mut tilemaps: Vec<Tilemap> = vec![];
     let sky1 = new_sky(data);
     tilemaps.push(sky1);
-    let mut walls: Vec<Wall> = vec![];
-    walls.push(Wall{rect:Rect{x: 0, y: -1, w: WIDTH as u16, h: 1}});
-    walls.push(Wall{rect:Rect{x: 0, y: HEIGHT as i32, w: WIDTH as u16, h: 1}});
+    let walls: Vec<Wall> = vec![
+        Wall::new(Rect{x: 0, y: -1, w: WIDTH as u16, h: 1}).with_layers(BOUNDS, BIRD),
+        Wall::new(Rect{x: 0, y: HEIGHT as i32, w: WIDTH as u16, h: 1}).with_layers(BOUNDS, BIRD),
+    ];
 
-    let state = GameState {
+    GameState {
         // initial game state
-        player: player,
-        obstacles: obstacles,
-        accel_down: 0,
+        player,
+        pipes,
         finished: false,
         score: 0,
         score_tex: create_score_tex(&data.font, 0),
-        tilemaps: tilemaps,
-        walls: walls,
-    };
-    return state;
+        score_flash: Tween::new(SCORE_OUTLINE, SCORE_OUTLINE, Duration::from_millis(0)),
+        tilemaps,
+        walls,
+        collisions: CollisionWorld::new(),
+    }
 }
 
 fn create_score_tex(font: &fontdue::Font, score: usize) -> Rc<Texture> {
@@ -655,13 +814,13 @@ fn create_score_tex(font: &fontdue::Font, score: usize) -> Rc<Texture> {
     while i < score_string.len() {
         let digit = score_string.chars().nth(i).unwrap();
         let (metrics, bitmap) = font.rasterize(digit, font_size);
-        let mut score_tex = Texture::from_vec(bitmap, metrics.width, metrics.height, 1);
-        score_tex.convert_to_rgba();
+        // Glyphs stay greyscale coverage; bitblt_mask colours them in
+        let score_tex = Texture::with_format(bitmap, metrics.width, metrics.height, PixelFormat::L8);
         digit_textures.push(score_tex);
         i += 1;
     }
     
-    return Rc::new(stack_horizontal(digit_textures));
+    Rc::new(stack_horizontal(digit_textures))
 }
 
 fn create_text_tex(font: &fontdue::Font, text: String) -> Rc<Texture> {
@@ -671,24 +830,12 @@ fn create_text_tex(font: &fontdue::Font, text: String) -> Rc<Texture> {
     while i < text.len() {
         let character = text.chars().nth(i).unwrap();
         let (metrics, bitmap) = font.rasterize(character, font_size);
-        let mut char_tex = Texture::from_vec(bitmap, metrics.width, metrics.height, 1);
-        char_tex.convert_to_rgba();
+        // Glyphs stay greyscale coverage; bitblt_mask colours them in
+        let char_tex = Texture::with_format(bitmap, metrics.width, metrics.height, PixelFormat::L8);
         char_textures.push(char_tex);
         i += 1;
     }
-    return Rc::new(stack_horizontal(char_textures));
-}
-
-fn scale_range(value: i32, value_min: f32, value_max: f32, scale_min:f32, scale_max:f32) -> i32{
-    let mut scaled: f32 = value as f32;
-    scaled = (scaled - value_min) / (value_max - value_min) * (scale_max - scale_min) + scale_min;
-    if scaled < scale_min {
-        scaled = scale_min;
-    }
-    if scaled > scale_max {
-        scaled = scale_max;
-    }
-    return scaled as i32;
+    Rc::new(stack_horizontal(char_textures))
 }
 
 fn new_sky(data: &GameData) -> Tilemap {
@@ -698,7 +845,7 @@ fn new_sky(data: &GameData) -> Tilemap {
     let mut tiles:Vec<Tile> = vec![];
     let mut i = 0;
     while i < tile_types {
-        let new_tile = Tile{oppgrid: true, opphit: false, myship: false};
+        let new_tile = Tile{oppgrid: true, opphit: false, myship: false, collision: TileCollision::Empty};
         tiles.push(new_tile);
         i += 1;
     }
@@ -712,5 +859,5 @@ fn new_sky(data: &GameData) -> Tilemap {
         i += 1;
     }
     
-    return Tilemap::new(position, dims, &Rc::new(tileset), map);
+    Tilemap::new(position, dims, &Rc::new(tileset), map)
 }
\ No newline at end of file
@@ -1,39 +1,527 @@
+use crate::shape::{self, Shape};
+use crate::texture::Texture;
+use crate::tiles::{TileCollision, Tilemap, TILE_SZ};
 use crate::types::*;
+use std::collections::HashMap;
+use std::rc::Rc;
 
+// Every layer, for masks that hit everything
+pub const ALL_LAYERS: u32 = u32::MAX;
 
+/// Which pixels of a picture are solid, one bit each, for pixel-perfect collision.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Mask {
+    w: u16,
+    h: u16,
+    // rows of bits, each row starting on a fresh word
+    words_per_row: usize,
+    bits: Vec<u64>,
+}
+
+#[allow(dead_code)]
+impl Mask {
+    // Nothing solid
+    pub fn new(w: u16, h: u16) -> Self {
+        let words_per_row = (w as usize).div_ceil(64);
+        Self { w, h, words_per_row, bits: vec![0; words_per_row * h as usize] }
+    }
+    /// Solid wherever the `frame` part of `texture` isn't fully transparent.
+    pub fn from_texture(texture: &Texture, frame: Rect) -> Self {
+        let mut mask = Self::new(frame.w, frame.h);
+        for y in 0..frame.h as i32 {
+            for x in 0..frame.w as i32 {
+                if texture.pixel((frame.x + x) as usize, (frame.y + y) as usize).3 > 0 {
+                    mask.set(x, y, true);
+                }
+            }
+        }
+        mask
+    }
+    pub fn size(&self) -> (u16, u16) {
+        (self.w, self.h)
+    }
+    // Outside the mask is never solid
+    pub fn get(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.w as i32 || y >= self.h as i32 {
+            return false;
+        }
+        let word = self.bits[y as usize * self.words_per_row + x as usize / 64];
+        word & (1 << (x % 64)) != 0
+    }
+    pub fn set(&mut self, x: i32, y: i32, solid: bool) {
+        if x < 0 || y < 0 || x >= self.w as i32 || y >= self.h as i32 {
+            return;
+        }
+        let word = &mut self.bits[y as usize * self.words_per_row + x as usize / 64];
+        if solid {
+            *word |= 1 << (x % 64);
+        } else {
+            *word &= !(1 << (x % 64));
+        }
+    }
+    // The 64 bits of row y starting at x, lowest bit first; anything off the mask is empty
+    fn row_bits(&self, x: i32, y: i32) -> u64 {
+        if y < 0 || y >= self.h as i32 {
+            return 0;
+        }
+        let row = &self.bits[y as usize * self.words_per_row..(y as usize + 1) * self.words_per_row];
+        let word = |i: i32| if i < 0 || i as usize >= row.len() { 0 } else { row[i as usize] };
+        let (i, shift) = (x.div_euclid(64), x.rem_euclid(64));
+        if shift == 0 {
+            word(i)
+        } else {
+            word(i) >> shift | word(i + 1) << (64 - shift)
+        }
+    }
+}
+
+/// A mask placed on a collider: its top left corner goes at `offset` from the top left of the
+/// collider's rect. Only the part inside the rect counts, since the rect is checked first.
+/// Two colliders with masks only touch if some solid pixels overlap; against a collider
+/// without one, the rects are all that matter.
+/// The pushout still comes from the rects (or shapes), not the pixels, so a masked collider
+/// gets pushed all the way out of the other one's rect even if only a corner pixel touched.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct PixelMask {
+    pub mask: Rc<Mask>,
+    pub offset: Vec2i,
+}
+
+impl PixelMask {
+    pub fn new(mask: Rc<Mask>, offset: Vec2i) -> Self {
+        Self { mask, offset }
+    }
+}
+
+// Whether any solid pixels of the two overlap, inside both rects
+fn pixels_overlap(a: Rect, a_pixels: &PixelMask, b: Rect, b_pixels: &PixelMask) -> bool {
+    let x0 = a.x.max(b.x);
+    let y0 = a.y.max(b.y);
+    let x1 = (a.x + a.w as i32).min(b.x + b.w as i32);
+    let y1 = (a.y + a.h as i32).min(b.y + b.h as i32);
+    let (ax, ay) = (a.x + a_pixels.offset.0, a.y + a_pixels.offset.1);
+    let (bx, by) = (b.x + b_pixels.offset.0, b.y + b_pixels.offset.1);
+    // A whole word of each row at a time, lined up on the overlap's left edge
+    (y0..y1).any(|y| {
+        (x0..x1).step_by(64).any(|x| {
+            let width = (x1 - x).min(64);
+            let in_overlap = if width == 64 { u64::MAX } else { (1 << width) - 1 };
+            a_pixels.mask.row_bits(x - ax, y - ay) & b_pixels.mask.row_bits(x - bx, y - by) & in_overlap != 0
+        })
+    })
+}
+
+#[derive(PartialEq, Clone, Debug)]
 pub struct Wall {
     pub rect: Rect,
+    // Bit flags: which layers this is on, and which layers it collides with.
+    // Two things only touch if each one's mask has a layer of the other one.
+    pub layer: u32,
+    pub mask: u32,
+    // Triggers report overlaps instead of pushing anything out
+    pub trigger: bool,
+    // Collide by solid pixels instead of the whole rect
+    pub pixels: Option<PixelMask>,
+    // Round or slanted outline inside the rect
+    pub shape: Shape,
+}
+
+#[allow(dead_code)]
+impl Wall {
+    pub fn new(rect: Rect) -> Self {
+        Self { rect, layer: 1, mask: ALL_LAYERS, trigger: false, pixels: None, shape: Shape::Rect }
+    }
+    pub fn with_shape(mut self, shape: Shape) -> Self {
+        self.shape = shape;
+        self
+    }
+    pub fn with_pixels(mut self, pixels: PixelMask) -> Self {
+        self.pixels = Some(pixels);
+        self
+    }
+    pub fn with_layers(mut self, layer: u32, mask: u32) -> Self {
+        self.layer = layer;
+        self.mask = mask;
+        self
+    }
+    pub fn as_trigger(mut self) -> Self {
+        self.trigger = true;
+        self
+    }
 }
 
 pub struct Mobile {
+    // The whole pixels it covers, which is what collides and gets drawn
     pub rect: Rect,
-    pub vx: i32,
-    pub vy: i32,
+    // Pixels per frame, finer than whole pixels
+    pub vx: Fixed,
+    pub vy: Fixed,
+    // How far past `rect` it really is, up to just under a pixel right and down
+    pub sub: (Fixed, Fixed),
+    // Same as for walls
+    pub layer: u32,
+    pub mask: u32,
+    pub trigger: bool,
+    pub pixels: Option<PixelMask>,
+    pub shape: Shape,
+    // 1/mass, so zero is something nothing can push, like a wall that moves
+    pub inv_mass: Fixed,
+    // How much speed it keeps when it hits something: zero stops dead, one bounces right back
+    pub restitution: Fixed,
+    // How much rubbing along a surface slows it down
+    pub friction: Fixed,
 }
 
 #[allow(dead_code)]
 impl Mobile {
+    // Whole pixels per frame to start with; set `vx` and `vy` for anything finer
     pub fn new(rect: Rect, vx: i32, vy: i32) -> Self{
         Self {
-            rect, vx, vy
+            rect,
+            vx: Fixed::from_int(vx),
+            vy: Fixed::from_int(vy),
+            sub: (Fixed::ZERO, Fixed::ZERO),
+            layer: 1,
+            mask: ALL_LAYERS,
+            trigger: false,
+            pixels: None,
+            shape: Shape::Rect,
+            inv_mass: Fixed::ONE,
+            restitution: Fixed::ZERO,
+            friction: Fixed::ZERO,
+        }
+    }
+    // 0 or less means it can't be pushed at all
+    pub fn with_mass(mut self, mass: f32) -> Self {
+        self.inv_mass = if mass > 0.0 { Fixed::from_f32(1.0 / mass) } else { Fixed::ZERO };
+        self
+    }
+    pub fn with_restitution(mut self, restitution: f32) -> Self {
+        self.restitution = Fixed::from_f32(restitution);
+        self
+    }
+    pub fn with_friction(mut self, friction: f32) -> Self {
+        self.friction = Fixed::from_f32(friction);
+        self
+    }
+    pub fn with_layers(mut self, layer: u32, mask: u32) -> Self {
+        self.layer = layer;
+        self.mask = mask;
+        self
+    }
+    pub fn as_trigger(mut self) -> Self {
+        self.trigger = true;
+        self
+    }
+    pub fn with_pixels(mut self, pixels: PixelMask) -> Self {
+        self.pixels = Some(pixels);
+        self
+    }
+    pub fn with_shape(mut self, shape: Shape) -> Self {
+        self.shape = shape;
+        self
+    }
+    /// Where it really is, in between pixels.
+    pub fn position(&self) -> (Fixed, Fixed) {
+        (Fixed::from_int(self.rect.x) + self.sub.0, Fixed::from_int(self.rect.y) + self.sub.1)
+    }
+    pub fn set_position(&mut self, (x, y): (Fixed, Fixed)) {
+        self.rect.x = x.floor();
+        self.rect.y = y.floor();
+        self.sub = (x.fract(), y.fract());
+    }
+    // Pushed out of something by whole pixels; it's flush against it now, so whatever was
+    // left over between pixels along the push goes
+    fn push(&mut self, x: i32, y: i32) {
+        self.rect.translate(x, y);
+        if x != 0 {
+            self.sub.0 = Fixed::ZERO;
+        }
+        if y != 0 {
+            self.sub.1 = Fixed::ZERO;
         }
     }
     pub fn update(&mut self) {
-        self.rect.translate(self.vx, self.vy)  
+        let (dx, dy) = self.take_steps();
+        self.rect.translate(dx, dy)  
+    }
+    // Add a frame's velocity to where it is between pixels, and give back the whole pixels to move
+    fn take_steps(&mut self) -> (i32, i32) {
+        let (x, y) = (self.sub.0 + self.vx, self.sub.1 + self.vy);
+        self.sub = (x.fract(), y.fract());
+        (x.floor(), y.floor())
+    }
+    /// Like `update`, but stops at the first wall in the way and slides along it with whatever
+    /// movement is left, so nothing tunnels through thin walls however fast it goes.
+    /// Velocity is left alone; `CollisionWorld::step` bounces it off what it ends up touching.
+    /// Triggers, walls on layers it doesn't collide with, and triggering mobiles don't stop anything.
+    /// Shapes are swept as they are, but pixel masks only count when `step` pushes things out.
+    pub fn move_and_slide(&mut self, statics: &[Wall]) {
+        let (mut rx, mut ry) = self.take_steps();
+        // Hitting a wall, then sliding into a second one, then done
+        for _ in 0..3 {
+            if rx == 0 && ry == 0 {
+                return;
+            }
+            let rect = self.rect;
+            let hit = statics
+                .iter()
+                .filter(|w| !w.trigger && !self.trigger && collides((self.layer, self.mask), (w.layer, w.mask)))
+                .filter_map(|w| self.sweep_into(rect, (rx, ry), w).map(|(t, normal)| (t, normal, w)))
+                .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            let (t, normal, wall) = match hit {
+                Some(hit) => hit,
+                None => {
+                    self.rect.translate(rx, ry);
+                    return;
+                }
+            };
+            let side = Side::from_normal(normal);
+            if self.shape != Shape::Rect || wall.shape != Shape::Rect {
+                // Up to the outline but not into it, then along it with whatever's left
+                let (dx, dy) = ((rx as f32 * t) as i32, (ry as f32 * t) as i32);
+                self.rect.translate(dx, dy);
+                let (lx, ly) = ((rx - dx) as f32, (ry - dy) as f32);
+                let into = lx * normal.0 + ly * normal.1;
+                rx = (lx - into * normal.0).round() as i32;
+                ry = (ly - into * normal.1).round() as i32;
+                match side {
+                    Side::Left | Side::Right => self.sub.0 = Fixed::ZERO,
+                    Side::Top | Side::Bottom => self.sub.1 = Fixed::ZERO,
+                }
+                continue;
+            }
+            let wall = wall.rect;
+            // Right up against the wall on the axis it hit, as far as it got on the other one
+            match side {
+                Side::Left | Side::Right => {
+                    let dx = if side == Side::Right { wall.x - (rect.x + rect.w as i32) } else { wall.x + wall.w as i32 - rect.x };
+                    let dy = (ry as f32 * t).round() as i32;
+                    self.rect.translate(dx, dy);
+                    self.sub.0 = Fixed::ZERO;
+                    rx = 0;
+                    ry -= dy;
+                }
+                Side::Top | Side::Bottom => {
+                    let dx = (rx as f32 * t).round() as i32;
+                    let dy = if side == Side::Bottom { wall.y - (rect.y + rect.h as i32) } else { wall.y + wall.h as i32 - rect.y };
+                    self.rect.translate(dx, dy);
+                    self.sub.1 = Fixed::ZERO;
+                    rx -= dx;
+                    ry = 0;
+                }
+            }
+        }
+    }
+    // How far along a move of (rx, ry) it runs into `wall`, from 0.0 to 1.0, and which way the wall
+    // faces there. Like `sweep`, something it's already touching doesn't count.
+    fn sweep_into(&self, rect: Rect, (rx, ry): (i32, i32), wall: &Wall) -> Option<(f32, (f32, f32))> {
+        if self.shape == Shape::Rect && wall.shape == Shape::Rect {
+            return sweep(rect, (rx, ry), wall.rect).map(|(t, side)| {
+                let (nx, ny) = side.normal();
+                (t, (nx as f32, ny as f32))
+            });
+        }
+        if shape::overlap(&self.shape, rect, &wall.shape, wall.rect).is_some() {
+            return None;
+        }
+        let length = ((rx * rx + ry * ry) as f32).sqrt();
+        let dir = (rx as f32 / length, ry as f32 / length);
+        let (t, normal) = shape::shapecast(&self.shape, rect, dir, length, &wall.shape, wall.rect)?;
+        Some((t / length, normal))
+    }
+    /// Like `move_and_slide`, but against the tiles of `map` instead of walls, only looking at
+    /// the ones it covers. Solid tiles stop it, one-way tiles only when it lands on them, and
+    /// slopes hold the middle of its bottom on their floor (it doesn't slide down them). On the
+    /// ground, it steps up ledges up to half a tile high instead of stopping, like at the top
+    /// of a slope. Unlike `move_and_slide` it bounces the velocity off what it hits, the same way
+    /// `restitute` does. Goes by the rect, not the shape or pixels; triggers only get moved.
+    pub fn move_in_tilemap(&mut self, map: &Tilemap) -> TileContacts {
+        let mut contacts = TileContacts::default();
+        let (mut rx, mut ry) = self.take_steps();
+        if self.trigger {
+            self.rect.translate(rx, ry);
+            contacts.touching(map, self.rect);
+            return contacts;
+        }
+        let grounded = on_floor(map, self.rect);
+        // Less than a tile at a time, so nothing skips over one
+        let most = TILE_SZ as i32 - 1;
+        while rx != 0 {
+            let dx = rx.clamp(-most, most);
+            rx -= dx;
+            let mut moved = self.rect;
+            moved.translate(dx, 0);
+            let solids = tiles_in(map, moved, |t| t == TileCollision::Solid);
+            let top = match solids.iter().map(|c| c.y).min() {
+                Some(top) => top,
+                None => {
+                    self.rect = moved;
+                    continue;
+                }
+            };
+            let lift = moved.y + moved.h as i32 - top;
+            let mut stepped = moved;
+            stepped.translate(0, -lift);
+            if grounded && lift <= STEP_HEIGHT && tiles_in(map, stepped, |t| t == TileCollision::Solid).is_empty() {
+                self.rect = stepped;
+                continue;
+            }
+            let side = if dx > 0 {
+                self.rect.x = solids.iter().map(|c| c.x).min().unwrap() - self.rect.w as i32;
+                Side::Right
+            } else {
+                self.rect.x = solids.iter().map(|c| c.x + c.w as i32).max().unwrap();
+                Side::Left
+            };
+            contacts.wall = Some(side);
+            self.hit(side);
+            break;
+        }
+        while ry != 0 {
+            let dy = ry.clamp(-most, most);
+            ry -= dy;
+            let mut moved = self.rect;
+            moved.translate(0, dy);
+            if dy > 0 {
+                // One-way tiles count if it was above them before this bit of the move
+                let bottom = self.rect.y + self.rect.h as i32;
+                let floors = tiles_in(map, moved, |t| t == TileCollision::Solid || t == TileCollision::OneWay);
+                if let Some(top) = floors.iter().map(|c| c.y).filter(|y| *y >= bottom).min() {
+                    self.rect.y = top - self.rect.h as i32;
+                    contacts.ground = true;
+                    self.hit(Side::Bottom);
+                    break;
+                }
+            } else {
+                let ceilings = tiles_in(map, moved, |t| t == TileCollision::Solid);
+                if let Some(bottom) = ceilings.iter().map(|c| c.y + c.h as i32).max() {
+                    self.rect.y = bottom;
+                    contacts.ceiling = true;
+                    self.hit(Side::Top);
+                    break;
+                }
+            }
+            self.rect = moved;
+        }
+        // Onto a slope's floor if it's sunk into one, or if it was walking along and the floor dropped away a bit
+        if let Some(floor) = slope_floor(map, self.rect) {
+            let bottom = self.rect.y + self.rect.h as i32;
+            let reach = if grounded { STEP_HEIGHT + self.vx.abs().round() } else { 0 };
+            let mut snapped = self.rect;
+            snapped.y = floor - self.rect.h as i32;
+            // Not if it's just stepped up off the slope onto something solid
+            if self.vy >= Fixed::ZERO && bottom >= floor - reach && tiles_in(map, snapped, |t| t == TileCollision::Solid).is_empty() {
+                self.rect = snapped;
+                contacts.ground = true;
+                self.hit(Side::Bottom);
+            }
+        }
+        // Still standing on something without having to land on it again
+        if self.vy >= Fixed::ZERO && on_floor(map, self.rect) {
+            contacts.ground = true;
+        }
+        contacts.touching(map, self.rect);
+        contacts
+    }
+    // Ran into something immovable with `side` and ended up right against it: nothing left over
+    // between pixels that way, and the velocity bounces off
+    fn hit(&mut self, side: Side) {
+        match side {
+            Side::Left | Side::Right => self.sub.0 = Fixed::ZERO,
+            Side::Top | Side::Bottom => self.sub.1 = Fixed::ZERO,
+        }
+        let (nx, ny) = side.normal();
+        let normal = (Fixed::from_int(nx), Fixed::from_int(ny));
+        let (vx, vy) = bounce(normal, (self.vx, self.vy), (Fixed::ZERO, Fixed::ZERO), (Fixed::ONE, Fixed::ZERO), self.restitution, self.friction).0;
+        self.vx = vx;
+        self.vy = vy;
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
-enum ColliderID {
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug)]
+pub enum ColliderID {
+    // index into the walls
     Static(usize),
+    // index into the mobiles
     Dynamic(usize),
 }
 
+/// Which side of a collider touched the other one.
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
-struct Contact {
-    a: ColliderID,
-    b: ColliderID,
-    mtv: (i32, i32),
+pub enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Side {
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+            Side::Top => Side::Bottom,
+            Side::Bottom => Side::Top,
+        }
+    }
+    /// Which way something touching with this side gets pushed.
+    pub fn normal(self) -> (i32, i32) {
+        match self {
+            Side::Left => (1, 0),
+            Side::Right => (-1, 0),
+            Side::Top => (0, 1),
+            Side::Bottom => (0, -1),
+        }
+    }
+    // The side facing away from a push along `normal`, going by whichever way it mostly points
+    fn from_normal((x, y): (f32, f32)) -> Side {
+        if x.abs() > y.abs() {
+            if x > 0.0 { Side::Left } else { Side::Right }
+        } else if y > 0.0 {
+            Side::Top
+        } else {
+            Side::Bottom
+        }
+    }
+}
+
+// Whether two things with these (layer, mask)s touch at all
+fn collides((a_layer, a_mask): (u32, u32), (b_layer, b_mask): (u32, u32)) -> bool {
+    a_mask & b_layer != 0 && b_mask & a_layer != 0
+}
+
+/// Two colliders touching. `a` is always a mobile.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Contact {
+    pub a: ColliderID,
+    pub b: ColliderID,
+    // One of them is a trigger, so nobody gets pushed
+    pub trigger: bool,
+    // The side of `a` that touched `b`
+    pub side: Side,
+    // How far `a` has to move to get out of `b`, pointing away from `b`.
+    // Zero when they're only touching edges.
+    pub mtv: (i32, i32),
+    // Which way the mtv points, exactly; slanted for round and polygon shapes
+    pub normal: (f32, f32),
+}
+
+impl Contact {
+    /// The same contact seen from `b`; handy for mobile-mobile contacts.
+    pub fn flipped(&self) -> Contact {
+        Contact {
+            a: self.b,
+            b: self.a,
+            trigger: self.trigger,
+            side: self.side.opposite(),
+            mtv: (-self.mtv.0, -self.mtv.1),
+            normal: (-self.normal.0, -self.normal.1),
+        }
+    }
+    pub fn involves(&self, id: ColliderID) -> bool {
+        self.a == id || self.b == id
+    }
 }
 
 #[allow(dead_code)]
@@ -62,97 +550,822 @@ fn rect_displacement(r1: Rect, r2: Rect) -> Option<(i32, i32)> {
     }
 }
 
-// Here we will be using push() on into, so it can't be a slice
-#[allow(dead_code)]
-fn gather_contacts(statics: &[Wall], dynamics: &[Mobile], into: &mut Vec<Contact>) {
+/// Swept AABB: if `r1` moving by `v` runs into `r2`, how far along the move it first touches
+/// (0.0 to 1.0) and which side of `r1` hits. Rects already overlapping or only touching edges
+/// while sliding past don't count.
+pub fn sweep(r1: Rect, v: (i32, i32), r2: Rect) -> Option<(f32, Side)> {
+    let (x_entry, x_exit) = sweep_axis(r1.x, r1.w, r2.x, r2.w, v.0);
+    let (y_entry, y_exit) = sweep_axis(r1.y, r1.h, r2.y, r2.h, v.1);
+    let entry = x_entry.max(y_entry);
+    let exit = x_exit.min(y_exit);
+    if entry >= exit || !(0.0..=1.0).contains(&entry) {
+        return None;
+    }
+    let side = if x_entry > y_entry {
+        if v.0 > 0 { Side::Right } else { Side::Left }
+    } else if v.1 > 0 {
+        Side::Bottom
+    } else {
+        Side::Top
+    };
+    Some((entry, side))
+}
+
+// When the spans start and stop overlapping on one axis, as fractions of the move
+fn sweep_axis(a: i32, a_len: u16, b: i32, b_len: u16, v: i32) -> (f32, f32) {
+    let (a_end, b_end) = (a + a_len as i32, b + b_len as i32);
+    if v == 0 {
+        return if a < b_end && b < a_end {
+            (f32::NEG_INFINITY, f32::INFINITY)
+        } else {
+            (f32::INFINITY, f32::NEG_INFINITY)
+        };
+    }
+    let v = v as f32;
+    if v > 0.0 {
+        ((b - a_end) as f32 / v, (b_end - a) as f32 / v)
+    } else {
+        ((b_end - a) as f32 / v, (b - a_end) as f32 / v)
+    }
+}
+
+// Which way to push r1 out of r2, along the axis where they overlap least
+fn rect_contact(r1: Rect, r2: Rect) -> Option<(Side, (i32, i32))> {
+    let overlap = rect_displacement(r1, r2)?;
+    // Twice the distance between the centers, to stay in whole numbers
+    let dx = (2 * r1.x + r1.w as i32) - (2 * r2.x + r2.w as i32);
+    let dy = (2 * r1.y + r1.h as i32) - (2 * r2.y + r2.h as i32);
+    Some(if overlap.0 > overlap.1 {
+        if dy < 0 { (Side::Bottom, (0, -overlap.1)) } else { (Side::Top, (0, overlap.1)) }
+    } else if dx < 0 {
+        (Side::Right, (-overlap.0, 0))
+    } else {
+        (Side::Left, (overlap.0, 0))
+    })
+}
+
+// What the narrowphase needs from a wall or a mobile
+struct Body<'a> {
+    rect: Rect,
+    shape: &'a Shape,
+    pixels: &'a Option<PixelMask>,
+}
+
+impl Wall {
+    fn body(&self) -> Body<'_> {
+        Body { rect: self.rect, shape: &self.shape, pixels: &self.pixels }
+    }
+}
+
+impl Mobile {
+    fn body(&self) -> Body<'_> {
+        Body { rect: self.rect, shape: &self.shape, pixels: &self.pixels }
+    }
+}
+
+// Side, mtv and normal of a touching a, b
+type Hit = (Side, (i32, i32), (f32, f32));
+
+// The rects first, then the shapes if they aren't both plain rects, then the pixels if both have masks
+fn collider_contact(a: Body, b: Body) -> Option<Hit> {
+    let hit = match (a.shape, b.shape) {
+        (Shape::Rect, Shape::Rect) => {
+            let (side, mtv) = rect_contact(a.rect, b.rect)?;
+            let (nx, ny) = side.normal();
+            (side, mtv, (nx as f32, ny as f32))
+        }
+        _ => {
+            rect_displacement(a.rect, b.rect)?;
+            let (normal, depth) = shape::overlap(a.shape, a.rect, b.shape, b.rect)?;
+            let mtv = ((normal.0 * depth).round() as i32, (normal.1 * depth).round() as i32);
+            (Side::from_normal(normal), mtv, normal)
+        }
+    };
+    match (a.pixels, b.pixels) {
+        (Some(a_pixels), Some(b_pixels)) if !pixels_overlap(a.rect, a_pixels, b.rect, b_pixels) => None,
+        _ => Some(hit),
+    }
+}
+
+// Here we will be using push() on into, so it can't be a slice.
+// Checks every pair; SpatialHash::gather_contacts finds the same contacts faster when there are lots of things
+pub fn gather_contacts(statics: &[Wall], dynamics: &[Mobile], into: &mut Vec<Contact>) {
     // collide mobiles against mobiles
     for (ai, a) in dynamics.iter().enumerate() {
         for (bi, b) in dynamics.iter().enumerate().skip(ai + 1) {
-            if rect_touching(a.rect, b.rect) {
-                let overlap = rect_displacement(a.rect, b.rect).unwrap();
-                let mut mtv = (0, 0);
-                if overlap.0 > overlap.1 {
-                    mtv.1 = overlap.1;
-                } else {
-                    mtv.0 = overlap.0;
-                }
-                into.push(Contact {
-                    a: ColliderID::Dynamic(ai),
-                    b: ColliderID::Dynamic(bi),
-                    mtv,
-                });
+            if !collides((a.layer, a.mask), (b.layer, b.mask)) {
+                continue;
+            }
+            if let Some((side, mtv, normal)) = collider_contact(a.body(), b.body()) {
+                let trigger = a.trigger || b.trigger;
+                into.push(Contact { a: ColliderID::Dynamic(ai), b: ColliderID::Dynamic(bi), trigger, side, mtv, normal });
             }
         }
     }
     // collide mobiles against walls
     for (ai, a) in dynamics.iter().enumerate() {
         for (bi, b) in statics.iter().enumerate() {
-            if rect_touching(a.rect, b.rect) {
-                let overlap = rect_displacement(a.rect, b.rect).unwrap();
-                let mut mtv = (0, 0);
-                if overlap.0 > overlap.1 {
-                    mtv.1 = overlap.1;
-                } else {
-                    mtv.0 = overlap.0;
+            if !collides((a.layer, a.mask), (b.layer, b.mask)) {
+                continue;
+            }
+            if let Some((side, mtv, normal)) = collider_contact(a.body(), b.body()) {
+                let trigger = a.trigger || b.trigger;
+                into.push(Contact { a: ColliderID::Dynamic(ai), b: ColliderID::Static(bi), trigger, side, mtv, normal });
+            }
+        }
+    }
+}
+
+/// Push overlapping mobiles out of walls and apart from each other, biggest overlaps first,
+/// and bounce their velocities off whatever they hit. Sorts `contacts` that way too.
+///
+/// Two mobiles split the push by their inverse masses, so a light one moves further,
+/// and one with `inv_mass` 0 doesn't move at all. Walls act like infinitely heavy mobiles.
+/// Trigger contacts are left alone.
+pub fn restitute(statics: &[Wall], dynamics: &mut [Mobile], contacts: &mut [Contact]) {
+    contacts.sort_by_key(|c| -(c.mtv.0 * c.mtv.0 + c.mtv.1 * c.mtv.1));
+    for contact in contacts.iter().filter(|c| !c.trigger) {
+        let index_a = match contact.a {
+            ColliderID::Dynamic(index_a) => index_a,
+            ColliderID::Static(_) => continue,
+        };
+        match contact.b {
+            ColliderID::Static(index_b) => {
+                // Earlier pushes may have moved this one already, so look again
+                let (_, (x, y), normal) = match collider_contact(dynamics[index_a].body(), statics[index_b].body()) {
+                    Some(c) => c,
+                    None => continue,
+                };
+                let obj_a = &mut dynamics[index_a];
+                obj_a.push(x, y);
+                let (restitution, friction) = (obj_a.restitution, obj_a.friction);
+                let (vx, vy) = bounce(fixed_normal(normal), (obj_a.vx, obj_a.vy), (Fixed::ZERO, Fixed::ZERO), (Fixed::ONE, Fixed::ZERO), restitution, friction).0;
+                obj_a.vx = vx;
+                obj_a.vy = vy;
+            }
+            ColliderID::Dynamic(index_b) => {
+                let (_, (x, y), normal) = match collider_contact(dynamics[index_a].body(), dynamics[index_b].body()) {
+                    Some(c) => c,
+                    None => continue,
+                };
+                let (ia, ib) = (dynamics[index_a].inv_mass, dynamics[index_b].inv_mass);
+                if ia + ib <= Fixed::ZERO {
+                    continue;
                 }
-                into.push(Contact {
-                    a: ColliderID::Dynamic(ai),
-                    b: ColliderID::Static(bi),
-                    mtv,
-                });
+                // Each goes its share of the way; a rounds and b takes the rest
+                let share = ia / (ia + ib);
+                let (ax, ay) = ((Fixed::from_int(x) * share).round(), (Fixed::from_int(y) * share).round());
+                dynamics[index_a].push(ax, ay);
+                dynamics[index_b].push(ax - x, ay - y);
+                let (a, b) = (&dynamics[index_a], &dynamics[index_b]);
+                // The bouncier one decides the bounce, both have to be rough for much friction
+                let restitution = a.restitution.max(b.restitution);
+                let friction = (a.friction * b.friction).sqrt();
+                let (va, vb) = bounce(fixed_normal(normal), (a.vx, a.vy), (b.vx, b.vy), (ia, ib), restitution, friction);
+                dynamics[index_a].vx = va.0;
+                dynamics[index_a].vy = va.1;
+                dynamics[index_b].vx = vb.0;
+                dynamics[index_b].vy = vb.1;
             }
         }
     }
 }
 
+// Shapes work out normals in f32; the rest of the bounce is done in Fixed like everything else that moves
+fn fixed_normal((nx, ny): (f32, f32)) -> (Fixed, Fixed) {
+    (Fixed::from_f32(nx), Fixed::from_f32(ny))
+}
+
+// New velocities for a and b after an impulse along `normal` (pointing from b to a) if they're
+// closing in, plus friction along the surface. An inverse mass of 0 keeps that velocity as it is.
+fn bounce((nx, ny): (Fixed, Fixed), va: (Fixed, Fixed), vb: (Fixed, Fixed), (ia, ib): (Fixed, Fixed), restitution: Fixed, friction: Fixed) -> ((Fixed, Fixed), (Fixed, Fixed)) {
+    // The normal is a unit vector, so the tangent is just it turned
+    let (tx, ty) = (-ny, nx);
+    let (rx, ry) = (va.0 - vb.0, va.1 - vb.1);
+    let closing = rx * nx + ry * ny;
+    if closing >= Fixed::ZERO {
+        return (va, vb);
+    }
+    let j = -(Fixed::ONE + restitution) * closing / (ia + ib);
+    // Friction can take away at most `friction` times the push, and can't reverse the sliding
+    let sliding = rx * tx + ry * ty;
+    let jt = (-sliding / (ia + ib)).clamp(-friction * j, friction * j);
+    let (ix, iy) = (j * nx + jt * tx, j * ny + jt * ty);
+    let va = (va.0 + ix * ia, va.1 + iy * ia);
+    let vb = (vb.0 - ix * ib, vb.1 - iy * ib);
+    (va, vb)
+}
+
+/// What a mobile ended up touching after `Mobile::move_in_tilemap`.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct TileContacts {
+    // Landed on or standing on a solid, one-way or slope tile
+    pub ground: bool,
+    pub ceiling: bool,
+    // Which side of the mobile ran into a solid tile
+    pub wall: Option<Side>,
+    // Overlapping any ladder or hazard tiles
+    pub ladder: bool,
+    pub hazard: bool,
+}
+
+impl TileContacts {
+    fn touching(&mut self, map: &Tilemap, r: Rect) {
+        self.ladder = !tiles_in(map, r, |t| t == TileCollision::Ladder).is_empty();
+        self.hazard = !tiles_in(map, r, |t| t == TileCollision::Hazard).is_empty();
+    }
+}
+
+// How high a ledge a mobile on the ground walks up onto
+const STEP_HEIGHT: i32 = TILE_SZ as i32 / 2;
+
+// The tiles of the kinds `keep` likes that `r` overlaps
+fn tiles_in(map: &Tilemap, r: Rect, keep: impl Fn(TileCollision) -> bool) -> Vec<Rect> {
+    let (cols, rows) = map.cells_under(r);
+    let mut tiles = vec![];
+    for row in rows {
+        for col in cols.clone() {
+            if keep(map.collision_at(col, row)) {
+                tiles.push(map.cell_rect(col, row));
+            }
+        }
+    }
+    tiles
+}
+
+// Where the floor of the slope under the middle of r's bottom is, looking in the tile its
+// bottom row of pixels is in and the one below
+fn slope_floor(map: &Tilemap, r: Rect) -> Option<i32> {
+    let (x, bottom) = (r.x + r.w as i32 / 2, r.y + r.h as i32);
+    let (cols, rows) = map.cells_under(Rect { x, y: bottom - 1, w: 1, h: 2 });
+    let col = *cols.start();
+    rows.map(|row| (map.collision_at(col, row), map.cell_rect(col, row)))
+        .find(|(t, _)| matches!(t, TileCollision::Slope { .. }))
+        .map(|(t, cell)| cell.y + cell.h as i32 - t.height_at(x - cell.x))
+}
+
+// Whether r is standing right on top of something
+fn on_floor(map: &Tilemap, r: Rect) -> bool {
+    let bottom = r.y + r.h as i32;
+    let below = Rect { x: r.x, y: bottom, w: r.w, h: 1 };
+    let floors = tiles_in(map, below, |t| t == TileCollision::Solid || t == TileCollision::OneWay);
+    floors.iter().any(|c| c.y == bottom) || slope_floor(map, r) == Some(bottom)
+}
+
+/// Broadphase: buckets colliders by which square cells of the world they cover,
+/// so finding what's near something only looks at what shares a cell with it.
+/// Cells should be about the size of the usual mobile.
+pub struct SpatialHash {
+    cell_size: i32,
+    cells: HashMap<(i32, i32), Vec<ColliderID>>,
+    // scratch space for gather_contacts, kept to save allocating every step
+    nearby: Vec<ColliderID>,
+    wall_contacts: Vec<Contact>,
+}
+
 #[allow(dead_code)]
-fn restitute(statics: &[Wall], dynamics: &mut [Mobile], contacts: &mut [Contact]) {
-    // handle restitution of dynamics against dynamics and dynamics against statics wrt contacts.
-    // You could instead make contacts `Vec<Contact>` if you think you might remove contacts.
-    // You could also add an additional parameter, a slice or vec representing how far we've displaced each dynamic, to avoid allocations if you track a vec of how far things have been moved.
-    // You might also want to pass in another &mut Vec<Contact> to be filled in with "real" touches that actually happened.
-    contacts.sort_unstable_by_key(|c| -(c.mtv.0 * c.mtv.0 + c.mtv.1 * c.mtv.1));
-    for contact in contacts {
-        //just make a move cause its dynamic for sure
-        match contact.a {
-            ColliderID::Static(_) => {
-                println!("uhh");
-
-            }
-            ColliderID::Dynamic(index_a) => match contact.b {
-                ColliderID::Dynamic(_) => {
-                    println!("GAME OVER");
-
-                    //put in a trigger to call this in main to kill program or bring to new page
-                    //*control_flow = ControlFlow::Exit;
-                    return;
+impl SpatialHash {
+    pub fn new(cell_size: u16) -> Self {
+        Self {
+            cell_size: cell_size.max(1) as i32,
+            cells: HashMap::new(),
+            nearby: vec![],
+            wall_contacts: vec![],
+        }
+    }
+    pub fn clear(&mut self) {
+        // Hang on to the cells that were used last time; things don't move far in a step
+        self.cells.retain(|_, ids| {
+            let used = !ids.is_empty();
+            ids.clear();
+            used
+        });
+    }
+    pub fn insert(&mut self, id: ColliderID, rect: Rect) {
+        let (x0, y0, x1, y1) = self.cell_range(rect);
+        for cy in y0..=y1 {
+            for cx in x0..=x1 {
+                self.cells.entry((cx, cy)).or_default().push(id);
+            }
+        }
+    }
+    /// Everything sharing a cell with `rect`, each once and in order (walls first).
+    /// They might not actually touch it; check with `rect_touching`.
+    pub fn query(&self, rect: Rect, into: &mut Vec<ColliderID>) {
+        into.clear();
+        let (x0, y0, x1, y1) = self.cell_range(rect);
+        for cy in y0..=y1 {
+            for cx in x0..=x1 {
+                if let Some(ids) = self.cells.get(&(cx, cy)) {
+                    into.extend_from_slice(ids);
                 }
-                ColliderID::Static(index_b) => {
-                    let obj_a = &mut dynamics[index_a];
-                    let obj_b = &statics[index_b];
-                    let a_under = obj_a.rect.y > obj_b.rect.y;
-                    let a_totheright = obj_a.rect.x > obj_b.rect.x;
-                    let mut x_translate = contact.mtv.0;
-                    let mut y_translate = contact.mtv.1;
-                    if x_translate != 0 {
-                        obj_a.vx = 0;
-                    }
-                    if y_translate != 0 {
-                        obj_a.vy = 0;
+            }
+        }
+        into.sort_unstable();
+        into.dedup();
+    }
+    /// Same contacts in the same order as the free `gather_contacts`, but only checking things
+    /// that share a cell. Fills the hash with `statics` and `dynamics` first, so it can be
+    /// queried for them afterwards.
+    pub fn gather_contacts(&mut self, statics: &[Wall], dynamics: &[Mobile], into: &mut Vec<Contact>) {
+        self.clear();
+        for (i, wall) in statics.iter().enumerate() {
+            self.insert(ColliderID::Static(i), wall.rect);
+        }
+        for (i, mobile) in dynamics.iter().enumerate() {
+            self.insert(ColliderID::Dynamic(i), mobile.rect);
+        }
+        let mut nearby = std::mem::take(&mut self.nearby);
+        let mut wall_contacts = std::mem::take(&mut self.wall_contacts);
+        wall_contacts.clear();
+        for (ai, a) in dynamics.iter().enumerate() {
+            self.query(a.rect, &mut nearby);
+            for &b in nearby.iter() {
+                let (body, layers, trigger) = match b {
+                    // each pair of mobiles once
+                    ColliderID::Dynamic(bi) if bi > ai => {
+                        let m = &dynamics[bi];
+                        (m.body(), (m.layer, m.mask), m.trigger)
                     }
-                    if !a_under {
-                        y_translate *= -1;
+                    ColliderID::Dynamic(_) => continue,
+                    ColliderID::Static(bi) => {
+                        let w = &statics[bi];
+                        (w.body(), (w.layer, w.mask), w.trigger)
                     }
-                    if !a_totheright {
-                        x_translate *= -1;
+                };
+                if !collides((a.layer, a.mask), layers) {
+                    continue;
+                }
+                if let Some((side, mtv, normal)) = collider_contact(a.body(), body) {
+                    let contact = Contact { a: ColliderID::Dynamic(ai), b, trigger: a.trigger || trigger, side, mtv, normal };
+                    match b {
+                        ColliderID::Dynamic(_) => into.push(contact),
+                        ColliderID::Static(_) => wall_contacts.push(contact),
                     }
-                    obj_a.rect.translate(x_translate, y_translate);
-                    // println!("{} by {}", x_translate, y_translate);
                 }
-            },
+            }
+        }
+        // mobiles against mobiles first, then against walls
+        into.extend_from_slice(&wall_contacts);
+        self.nearby = nearby;
+        self.wall_contacts = wall_contacts;
+    }
+
+    // First and last cells covered; the far edges count, since touching edges is a contact
+    fn cell_range(&self, rect: Rect) -> (i32, i32, i32, i32) {
+        let size = self.cell_size;
+        (
+            rect.x.div_euclid(size),
+            rect.y.div_euclid(size),
+            (rect.x + rect.w as i32).div_euclid(size),
+            (rect.y + rect.h as i32).div_euclid(size),
+        )
+    }
+}
+
+/// What happened with a trigger overlap this step.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Overlap {
+    Enter,
+    Stay,
+    Exit,
+}
+
+/// A mobile and something it overlaps, where one of the two is a trigger.
+/// Like in contacts, `a` is always the mobile.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct TriggerEvent {
+    pub a: ColliderID,
+    pub b: ColliderID,
+    pub overlap: Overlap,
+}
+
+/// Collision for a set of walls and mobiles; call `step` after moving the mobiles,
+/// or `advance` to have it move them without going through walls.
+pub struct CollisionWorld {
+    contacts: Vec<Contact>,
+    broadphase: SpatialHash,
+    triggers: Vec<TriggerEvent>,
+    // trigger pairs overlapping after the last step, sorted
+    overlapping: Vec<(ColliderID, ColliderID)>,
+}
+
+#[allow(dead_code)]
+impl CollisionWorld {
+    pub fn new() -> Self {
+        Self::with_cell_size(64)
+    }
+    // Broadphase cells about the size of the usual mobile work best
+    pub fn with_cell_size(cell_size: u16) -> Self {
+        Self { contacts: vec![], broadphase: SpatialHash::new(cell_size), triggers: vec![], overlapping: vec![] }
+    }
+    /// Find everything that's touching, push the mobiles out of whatever they overlap,
+    /// and return what touched what for the game to react to, biggest overlaps first.
+    /// Trigger overlaps go to `trigger_events` instead.
+    ///
+    /// Triggers are told apart between steps by their `ColliderID`s, so keep walls and mobiles
+    /// in the same order from step to step, or expect some extra enters and exits.
+    /// Taking walls out of the middle is fine as long as `remove_statics` hears about it.
+    pub fn step(&mut self, statics: &[Wall], dynamics: &mut [Mobile]) -> &[Contact] {
+        self.contacts.clear();
+        self.broadphase.gather_contacts(statics, dynamics, &mut self.contacts);
+        restitute(statics, dynamics, &mut self.contacts);
+        let mut now: Vec<(ColliderID, ColliderID)> = self.contacts.iter().filter(|c| c.trigger).map(|c| (c.a, c.b)).collect();
+        now.sort_unstable();
+        self.contacts.retain(|c| !c.trigger);
+        self.triggers.clear();
+        for &(a, b) in now.iter() {
+            let overlap = if self.overlapping.binary_search(&(a, b)).is_ok() { Overlap::Stay } else { Overlap::Enter };
+            self.triggers.push(TriggerEvent { a, b, overlap });
+        }
+        for &(a, b) in self.overlapping.iter() {
+            if now.binary_search(&(a, b)).is_err() {
+                self.triggers.push(TriggerEvent { a, b, overlap: Overlap::Exit });
+            }
+        }
+        self.overlapping = now;
+        &self.contacts
+    }
+    /// Forget the walls in `range` and move the ones after it down to fill the gap, the same
+    /// as draining them out of the walls passed to `step`. Overlaps with them just end, no `Exit`.
+    pub fn remove_statics(&mut self, range: std::ops::Range<usize>) {
+        let removed = range.len();
+        self.overlapping.retain(|(_, b)| !matches!(b, ColliderID::Static(i) if range.contains(i)));
+        for (_, b) in self.overlapping.iter_mut() {
+            if let ColliderID::Static(i) = b {
+                if *i >= range.end {
+                    *i -= removed;
+                }
+            }
+        }
+    }
+    /// Trigger enters, stays and exits from the last step.
+    pub fn trigger_events(&self) -> &[TriggerEvent] {
+        &self.triggers
+    }
+    /// Move every mobile by its velocity with `move_and_slide`, then `step`.
+    pub fn advance(&mut self, statics: &[Wall], dynamics: &mut [Mobile]) -> &[Contact] {
+        for mobile in dynamics.iter_mut() {
+            mobile.move_and_slide(statics);
         }
+        self.step(statics, dynamics)
     }
-    // Keep going!  Note that you can assume every contact has a dynamic object in .a.
-    // You might decide to tweak the interface of this function to separately take dynamic-static and dynamic-dynamic contacts, to avoid a branch inside of the response calculation.
-    // Or, you might decide to calculate signed mtvs taking direction into account instead of the unsigned displacements from rect_displacement up above.  Or calculate one MTV per involved entity, then apply displacements to both objects during restitution (sorting by the max or the sum of their magnitudes)
-}
\ No newline at end of file
+    /// Where everything was at the start of the last step, for finding what's near a spot.
+    pub fn broadphase(&self) -> &SpatialHash {
+        &self.broadphase
+    }
+    // The contacts from the last step
+    pub fn contacts(&self) -> &[Contact] {
+        &self.contacts
+    }
+}
+
+impl Default for CollisionWorld {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: u16, h: u16) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    #[test]
+    fn immovable_mobiles_make_the_other_one_take_the_whole_push() {
+        let mut mobiles = [Mobile::new(rect(0, 0, 10, 10), 0, 0), Mobile::new(rect(8, 0, 10, 10), 0, 0).with_mass(0.0)];
+        CollisionWorld::new().step(&[], &mut mobiles);
+        assert_eq!(mobiles[0].rect.x, -2);
+        assert_eq!(mobiles[1].rect.x, 8);
+        // The other way round
+        let mut mobiles = [Mobile::new(rect(0, 0, 10, 10), 0, 0).with_mass(0.0), Mobile::new(rect(8, 0, 10, 10), 0, 0)];
+        CollisionWorld::new().step(&[], &mut mobiles);
+        assert_eq!(mobiles[0].rect.x, 0);
+        assert_eq!(mobiles[1].rect.x, 10);
+    }
+
+    #[test]
+    fn equal_masses_split_the_push() {
+        let mut mobiles = [Mobile::new(rect(0, 0, 10, 10), 0, 0), Mobile::new(rect(6, 0, 10, 10), 0, 0)];
+        CollisionWorld::new().step(&[], &mut mobiles);
+        assert_eq!(mobiles[0].rect.x, -2);
+        assert_eq!(mobiles[1].rect.x, 8);
+        // A heavier one moves less
+        let mut mobiles = [Mobile::new(rect(0, 0, 10, 10), 0, 0), Mobile::new(rect(6, 0, 10, 10), 0, 0).with_mass(3.0)];
+        CollisionWorld::new().step(&[], &mut mobiles);
+        assert_eq!(mobiles[0].rect.x, -3);
+        assert_eq!(mobiles[1].rect.x, 7);
+    }
+
+    // Landing 2 pixels into a floor at 4 pixels a frame down, and `vx` across
+    fn land(vx: i32, restitution: f32, friction: f32) -> Mobile {
+        let floor = [Wall::new(rect(0, 10, 40, 10))];
+        let mut mobiles = [Mobile::new(rect(10, 2, 10, 10), vx, 4).with_restitution(restitution).with_friction(friction)];
+        CollisionWorld::new().step(&floor, &mut mobiles);
+        let [mobile] = mobiles;
+        assert_eq!(mobile.rect.y, 0);
+        mobile
+    }
+
+    #[test]
+    fn restitution_decides_the_bounce() {
+        assert_eq!(land(0, 0.0, 0.0).vy, Fixed::ZERO);
+        assert_eq!(land(0, 1.0, 0.0).vy, Fixed::from_int(-4));
+        assert_eq!(land(0, 0.5, 0.0).vy, Fixed::from_int(-2));
+    }
+
+    #[test]
+    fn friction_slows_sliding_along_the_surface() {
+        assert_eq!(land(6, 0.0, 0.0).vx, Fixed::from_int(6));
+        // At most friction times the push (4) comes off
+        assert_eq!(land(6, 0.0, 0.5).vx, Fixed::from_int(4));
+        assert_eq!(land(6, 0.0, 1.0).vx, Fixed::from_int(2));
+        // but it never turns it around
+        assert_eq!(land(2, 0.0, 1.0).vx, Fixed::ZERO);
+        assert_eq!(land(-2, 0.0, 1.0).vx, Fixed::ZERO);
+    }
+
+    #[test]
+    fn fast_movers_stop_at_thin_walls() {
+        let walls = [Wall::new(rect(20, 0, 2, 10))];
+        let mut mobile = Mobile::new(rect(0, 2, 4, 4), 50, 0);
+        mobile.move_and_slide(&walls);
+        assert_eq!(mobile.rect.x, 16);
+        // Round walls too
+        let walls = [Wall::new(rect(20, 0, 4, 4)).with_shape(Shape::circle(2.0))];
+        let mut mobile = Mobile::new(rect(0, 0, 4, 4), 50, 0).with_shape(Shape::circle(2.0));
+        mobile.move_and_slide(&walls);
+        assert_eq!(mobile.rect.x, 16);
+        // Going by the outline, not the rect around it: clipping the bottom of a circle it
+        // gets pushed down around it instead of stopping where the rect starts
+        let walls = [Wall::new(rect(20, 0, 8, 8)).with_shape(Shape::circle(4.0))];
+        let mut mobile = Mobile::new(rect(0, 7, 4, 4), 50, 0);
+        mobile.move_and_slide(&walls);
+        assert!(mobile.rect.x > 16 && mobile.rect.y >= 8);
+        assert!(shape::overlap(&mobile.shape, mobile.rect, &walls[0].shape, walls[0].rect).is_none());
+    }
+
+    #[test]
+    fn sliding_keeps_the_motion_along_the_wall() {
+        // 6 down to the floor, with 6 of the 10 across done by then and the rest after
+        let floor = [Wall::new(rect(-20, 10, 60, 4))];
+        let mut mobile = Mobile::new(rect(0, 0, 4, 4), 10, 10);
+        mobile.move_and_slide(&floor);
+        assert_eq!((mobile.rect.x, mobile.rect.y), (10, 6));
+        // Up a 45 degree ramp, right into it and up along it
+        let ramp = [Wall::new(rect(10, 0, 20, 20)).with_shape(Shape::Polygon(vec![(0.0, 20.0), (20.0, 0.0), (20.0, 20.0)]))];
+        let mut mobile = Mobile::new(rect(0, 16, 4, 4), 12, 0);
+        mobile.move_and_slide(&ramp);
+        assert_eq!((mobile.rect.x, mobile.rect.y), (9, 13));
+    }
+
+    // The trigger events from stepping with a 4x4 mobile at `x`
+    fn trigger_steps(world: &mut CollisionWorld, walls: &[Wall], x: i32) -> Vec<TriggerEvent> {
+        let mut mobiles = [Mobile::new(rect(x, 0, 4, 4), 0, 0)];
+        assert!(world.step(walls, &mut mobiles).is_empty());
+        world.trigger_events().to_vec()
+    }
+
+    #[test]
+    fn triggers_enter_stay_and_exit() {
+        let walls = [Wall::new(rect(10, 0, 10, 10)).as_trigger()];
+        let mut world = CollisionWorld::new();
+        let event = |overlap| vec![TriggerEvent { a: ColliderID::Dynamic(0), b: ColliderID::Static(0), overlap }];
+        assert_eq!(trigger_steps(&mut world, &walls, 0), vec![]);
+        assert_eq!(trigger_steps(&mut world, &walls, 8), event(Overlap::Enter));
+        assert_eq!(trigger_steps(&mut world, &walls, 12), event(Overlap::Stay));
+        assert_eq!(trigger_steps(&mut world, &walls, 18), event(Overlap::Stay));
+        assert_eq!(trigger_steps(&mut world, &walls, 30), event(Overlap::Exit));
+        assert_eq!(trigger_steps(&mut world, &walls, 30), vec![]);
+    }
+
+    #[test]
+    fn triggers_go_by_layers_too() {
+        let mut world = CollisionWorld::new();
+        let walls = [Wall::new(rect(0, 0, 10, 10)).with_layers(2, ALL_LAYERS).as_trigger()];
+        let mut mobiles = [Mobile::new(rect(2, 2, 4, 4), 0, 0).with_layers(1, 1)];
+        world.step(&walls, &mut mobiles);
+        assert!(world.trigger_events().is_empty());
+        let mut mobiles = [Mobile::new(rect(2, 2, 4, 4), 0, 0).with_layers(1, 2)];
+        world.step(&walls, &mut mobiles);
+        assert_eq!(world.trigger_events().len(), 1);
+    }
+
+    #[test]
+    fn triggers_dont_push_or_bounce() {
+        let walls = [Wall::new(rect(0, 4, 20, 10)).as_trigger()];
+        let mut mobiles = [Mobile::new(rect(2, 2, 4, 4), 1, 3).with_restitution(1.0), Mobile::new(rect(4, 2, 4, 4), 0, 0).as_trigger()];
+        let mut world = CollisionWorld::new();
+        assert!(world.step(&walls, &mut mobiles).is_empty());
+        // Both in the wall, and in each other
+        assert_eq!(world.trigger_events().len(), 3);
+        assert_eq!((mobiles[0].rect.x, mobiles[0].rect.y), (2, 2));
+        assert_eq!((mobiles[0].vx, mobiles[0].vy), (Fixed::from_int(1), Fixed::from_int(3)));
+        assert_eq!((mobiles[1].rect.x, mobiles[1].rect.y), (4, 2));
+    }
+
+    #[test]
+    fn removed_walls_take_their_overlaps_with_them() {
+        // Like flappy's pipes: the first gap scrolls off and the next one moves down a slot
+        let walls = [Wall::new(rect(-40, 0, 10, 10)).as_trigger(), Wall::new(rect(0, 0, 10, 10)).as_trigger()];
+        let mut world = CollisionWorld::new();
+        trigger_steps(&mut world, &walls, 2);
+        world.remove_statics(0..1);
+        let stay = vec![TriggerEvent { a: ColliderID::Dynamic(0), b: ColliderID::Static(0), overlap: Overlap::Stay }];
+        assert_eq!(trigger_steps(&mut world, &walls[1..], 2), stay);
+    }
+
+    #[test]
+    fn spatial_hash_finds_the_same_pairs_as_checking_every_pair() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(7);
+        // Mostly small things on both sides of zero, and a few big ones spanning lots of cells
+        let random_rect = |rng: &mut StdRng| {
+            let (w, h) = if rng.gen_range(0, 10) == 0 { (rng.gen_range(50, 300), rng.gen_range(50, 300)) } else { (rng.gen_range(1, 20), rng.gen_range(1, 20)) };
+            rect(rng.gen_range(-300, 300), rng.gen_range(-300, 300), w, h)
+        };
+        let walls: Vec<Wall> = (0..60).map(|_| Wall::new(random_rect(&mut rng))).collect();
+        let mobiles: Vec<Mobile> = (0..200).map(|_| Mobile::new(random_rect(&mut rng), 0, 0)).collect();
+        let pairs = |contacts: &[Contact]| {
+            let mut pairs: Vec<(ColliderID, ColliderID)> = contacts.iter().map(|c| (c.a, c.b)).collect();
+            pairs.sort_unstable();
+            pairs
+        };
+        let mut brute = vec![];
+        gather_contacts(&walls, &mobiles, &mut brute);
+        assert!(brute.len() > 50);
+        for &cell_size in [1, 16, 64, 1000].iter() {
+            let mut hashed = vec![];
+            SpatialHash::new(cell_size).gather_contacts(&walls, &mobiles, &mut hashed);
+            assert_eq!(pairs(&hashed), pairs(&brute));
+            assert_eq!(hashed, brute);
+        }
+    }
+
+    // Solid below the diagonal from the top right to the bottom left, clear above it
+    fn triangle(size: u16) -> Rc<Mask> {
+        let mut mask = Mask::new(size, size);
+        for y in 0..size as i32 {
+            for x in size as i32 - 1 - y..size as i32 {
+                mask.set(x, y, true);
+            }
+        }
+        Rc::new(mask)
+    }
+
+    fn masked_contacts(wall: Rect, wall_mask: Rc<Mask>, mobile: Rect, mobile_mask: Rc<Mask>) -> usize {
+        let walls = vec![Wall::new(wall).with_pixels(PixelMask::new(wall_mask, Vec2i(0, 0)))];
+        let mobiles = vec![Mobile::new(mobile, 0, 0).with_pixels(PixelMask::new(mobile_mask, Vec2i(0, 0)))];
+        let mut contacts = vec![];
+        gather_contacts(&walls, &mobiles, &mut contacts);
+        contacts.len()
+    }
+
+    #[test]
+    fn transparent_corners_dont_touch() {
+        // The wall's clear top left corner overlaps the mobile's bottom right, which is solid
+        assert_eq!(masked_contacts(rect(0, 0, 100, 100), triangle(100), rect(-60, -60, 100, 100), triangle(100)), 0);
+        // Without masks those rects would have touched
+        let mut contacts = vec![];
+        gather_contacts(&[Wall::new(rect(0, 0, 100, 100))], &[Mobile::new(rect(-60, -60, 100, 100), 0, 0)], &mut contacts);
+        assert_eq!(contacts.len(), 1);
+    }
+
+    #[test]
+    fn opaque_pixels_touch() {
+        // The wall's solid bottom right corner under the mobile's solid bottom right corner
+        assert_eq!(masked_contacts(rect(0, 0, 100, 100), triangle(100), rect(30, 30, 100, 100), triangle(100)), 1);
+        // Only a single pixel of each overlapping, at the far end of a row past the first word
+        let mut wide = Mask::new(130, 1);
+        wide.set(129, 0, true);
+        let mut dot = Mask::new(1, 1);
+        dot.set(0, 0, true);
+        let (wide, dot) = (Rc::new(wide), Rc::new(dot));
+        assert_eq!(masked_contacts(rect(-7, 3, 130, 1), wide.clone(), rect(122, 3, 1, 1), dot.clone()), 1);
+        assert_eq!(masked_contacts(rect(-7, 3, 130, 1), wide, rect(121, 3, 1, 1), dot), 0);
+    }
+
+    #[test]
+    fn word_wise_overlap_matches_pixel_by_pixel() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(3);
+        let random_mask = |rng: &mut StdRng| {
+            let mut mask = Mask::new(rng.gen_range(1, 150), rng.gen_range(1, 4));
+            let (w, h) = mask.size();
+            for _ in 0..rng.gen_range(0, 6) {
+                mask.set(rng.gen_range(0, w as i32), rng.gen_range(0, h as i32), true);
+            }
+            PixelMask::new(Rc::new(mask), Vec2i(rng.gen_range(-3, 3), rng.gen_range(-1, 1)))
+        };
+        for _ in 0..2000 {
+            let (a_pixels, b_pixels) = (random_mask(&mut rng), random_mask(&mut rng));
+            let a = rect(rng.gen_range(-100, 100), rng.gen_range(-2, 2), rng.gen_range(1, 150), 4);
+            let b = rect(rng.gen_range(-100, 100), rng.gen_range(-2, 2), rng.gen_range(1, 150), 4);
+            let (ax, ay) = (a.x + a_pixels.offset.0, a.y + a_pixels.offset.1);
+            let (bx, by) = (b.x + b_pixels.offset.0, b.y + b_pixels.offset.1);
+            let by_pixel = (a.y.max(b.y)..(a.y + 4).min(b.y + 4)).any(|y| {
+                (a.x.max(b.x)..(a.x + a.w as i32).min(b.x + b.w as i32))
+                    .any(|x| a_pixels.mask.get(x - ax, y - ay) && b_pixels.mask.get(x - bx, y - by))
+            });
+            assert_eq!(pixels_overlap(a, &a_pixels, b, &b_pixels), by_pixel);
+        }
+    }
+
+    // A map from rows of characters: . empty, # solid, - one-way, / and \\ 45° slopes, and
+    // = and _ flat floors 8 and 7 pixels up a tile
+    fn tilemap(rows: &[&str]) -> Tilemap {
+        use crate::texture::PixelFormat;
+        use crate::tiles::{Tile, Tileset};
+        let kinds = [
+            ('.', TileCollision::Empty),
+            ('#', TileCollision::Solid),
+            ('-', TileCollision::OneWay),
+            ('/', TileCollision::Slope { left: 0, right: 16 }),
+            ('\\', TileCollision::Slope { left: 16, right: 0 }),
+            ('=', TileCollision::Slope { left: 8, right: 8 }),
+            ('_', TileCollision::Slope { left: 7, right: 7 }),
+        ];
+        let texture = Rc::new(Texture::with_format(vec![0; 128 * 16 * 4], 128, 16, PixelFormat::Rgba8));
+        let tileset = Rc::new(Tileset::new(
+            kinds.iter().map(|&(_, collision)| Tile { oppgrid: false, opphit: false, myship: false, collision }).collect(),
+            &texture,
+        ));
+        let ids = rows.iter().flat_map(|row| row.chars()).map(|c| kinds.iter().position(|k| k.0 == c).unwrap()).collect();
+        Tilemap::new(Vec2i(0, 0), (rows[0].len(), rows.len()), &tileset, ids)
+    }
+
+    // Walks an 8x8 mobile along at `vx` with a bit of gravity, like a platformer would,
+    // giving back where it was each frame
+    fn walk(map: &Tilemap, x: i32, y: i32, vx: i32, frames: usize) -> Vec<(Rect, TileContacts)> {
+        let mut mobile = Mobile::new(rect(x, y, 8, 8), vx, 0);
+        (0..frames)
+            .map(|_| {
+                mobile.vy += Fixed::from_frac(3, 8);
+                let contacts = mobile.move_in_tilemap(map);
+                (mobile.rect, contacts)
+            })
+            .collect()
+    }
+
+    // Heights go one way up to the top and the other way after, with no bobbing in between.
+    // Where a corner catches the flat top it can go up to half the mobile's width at once.
+    fn assert_smooth(ys: &[i32], top: i32) {
+        let peak = ys.iter().position(|&y| y == top).unwrap();
+        assert!(ys[..=peak].windows(2).all(|w| w[1] <= w[0] && w[0] - w[1] <= 4), "{:?}", ys);
+        assert!(ys[peak..].windows(2).all(|w| w[1] >= w[0] && w[1] - w[0] <= 4), "{:?}", ys);
+    }
+
+    #[test]
+    fn walking_over_slopes_doesnt_jitter() {
+        let map = tilemap(&["........", "........", "../##\\..", "########"]);
+        // Up the / and down the \\ going right
+        let steps = walk(&map, 0, 40, 1, 110);
+        assert!(steps.iter().all(|(_, c)| c.ground && c.wall.is_none()));
+        let ys: Vec<i32> = steps.iter().map(|(r, _)| r.y).collect();
+        assert_smooth(&ys, 24);
+        assert_eq!(*ys.last().unwrap(), 40);
+        // Up the \\ and down the / going left
+        let steps = walk(&map, 120, 40, -1, 110);
+        assert!(steps.iter().all(|(_, c)| c.ground && c.wall.is_none()));
+        let ys: Vec<i32> = steps.iter().map(|(r, _)| r.y).collect();
+        assert_smooth(&ys, 24);
+        assert_eq!(*ys.last().unwrap(), 40);
+    }
+
+    #[test]
+    fn one_way_tiles_let_things_up_through_and_land_them_on_top() {
+        let map = tilemap(&["....", "....", ".-..", "...."]);
+        // Jumping up from under it goes straight through
+        let mut mobile = Mobile::new(rect(16, 50, 8, 8), 0, -5);
+        let mut highest = mobile.rect.y;
+        for _ in 0..60 {
+            mobile.vy += Fixed::from_frac(1, 4);
+            let contacts = mobile.move_in_tilemap(&map);
+            assert!(!contacts.ceiling);
+            highest = highest.min(mobile.rect.y);
+        }
+        assert!(highest + 8 < 32);
+        // and on the way back down it stops on top
+        assert_eq!(mobile.rect.y, 32 - 8);
+        assert_eq!(mobile.vy, Fixed::ZERO);
+        assert!(mobile.move_in_tilemap(&map).ground);
+    }
+
+    #[test]
+    fn steps_up_ledges_up_to_half_a_tile() {
+        // Exactly STEP_HEIGHT up from the = floor onto the solid tile
+        let steps = walk(&tilemap(&["....", "....", "==#.", "####"]), 8, 32, 1, 30);
+        assert!(steps.iter().all(|(_, c)| c.ground && c.wall.is_none()));
+        assert_eq!(steps.last().unwrap().0.y, 32 - 8);
+        // A pixel higher from the _ floor, and it's a wall
+        let steps = walk(&tilemap(&["....", "....", "__#.", "####"]), 8, 33, 1, 30);
+        assert!(steps.iter().all(|(r, c)| r.y == 33 && r.x <= 32 - 8 && c.ground));
+        assert!(steps.iter().any(|(_, c)| c.wall == Some(Side::Right)));
+        assert_eq!(steps.last().unwrap().0.x, 32 - 8);
+    }
+}
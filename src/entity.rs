@@ -1,45 +1,112 @@
 use crate::collision::*;
+use crate::scene::*;
+use crate::skeleton::Skeleton;
 use crate::sprite::*;
 use crate::types::*;
+use std::rc::Rc;
 
 pub struct Entity {
     pub hitbox: Mobile,
     pub sprite: Sprite,
     pub gravity: bool,
+    // Extra parts like a bird's wing, placed relative to the sprite's anchor point
+    pub children: Vec<Node>,
+    // Moves the children around by name, if there is one
+    pub skeleton: Option<Skeleton>,
+    // Pixel masks for every frame of every animation, if the entity collides by its sprite's pixels
+    pub masks: Vec<Vec<Rc<Mask>>>,
 }
 
 impl Entity {
     pub fn new(hitbox:Mobile, sprite:Sprite, gravity:bool) -> Self {
-        Self {hitbox, sprite, gravity}
+        Self {hitbox, sprite, gravity, children: vec![], skeleton: None, masks: vec![]}
+    }
+    /// Collide by the solid pixels of the sprite's current frame instead of the whole hitbox.
+    /// The hitbox should cover the sprite, since it's checked first. Flipping and scaling are ignored.
+    pub fn with_pixel_masks(mut self) -> Self {
+        let texture = Rc::clone(self.sprite.texture());
+        self.masks = self.sprite.animations.iter()
+            .map(|anim| anim.frames().iter().map(|f| Rc::new(Mask::from_texture(&texture, f.rect))).collect())
+            .collect();
+        self.update_pixels();
+        self
+    }
+    // Put the current frame's mask on the hitbox, lined up with where the sprite is drawn
+    fn update_pixels(&mut self) {
+        let anim = &self.sprite.animations[self.sprite.current_animation];
+        let mask = match self.masks.get(self.sprite.current_animation).and_then(|m| m.get(anim.current_frame() as usize)) {
+            Some(mask) => Rc::clone(mask),
+            None => return,
+        };
+        let Vec2i(ax, ay) = self.anchor_point();
+        let Vec2i(px, py) = self.sprite.pivot();
+        let offset = Vec2i(ax - px - self.hitbox.rect.x, ay - py - self.hitbox.rect.y);
+        self.hitbox.pixels = Some(PixelMask::new(mask, offset));
+    }
+    pub fn add_child(&mut self, child: Node) {
+        self.children.push(child);
+    }
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Node> {
+        self.children.iter_mut().find_map(|c| c.find_mut(name))
+    }
+    // The sprite of the part called `name`, if it has one
+    pub fn sprite_mut(&mut self, name: &str) -> Option<&mut Sprite> {
+        self.child_mut(name).and_then(|c| c.sprite.as_mut())
+    }
+    pub fn advance(&mut self, dt: f64) {
+        self.sprite.advance(dt);
+        self.update_pixels();
+        if let Some(skeleton) = self.skeleton.as_mut() {
+            skeleton.advance(dt);
+            skeleton.pose(&mut self.children);
+        }
+        for child in self.children.iter_mut() {
+            child.advance(dt);
+        }
+    }
+    // The entity's own events first, then its parts'
+    pub fn take_events(&mut self) -> Vec<String> {
+        let mut events = self.sprite.take_events();
+        for child in self.children.iter_mut() {
+            events.extend(child.take_events());
+        }
+        events
+    }
+    // Where the sprite goes: the point of the hitbox matching the sprite's anchor, so an anchor
+    // of (0.5, 0.5) centers the sprite on the hitbox and (0.5, 1.0) stands it on the hitbox's bottom
+    pub fn anchor_point(&self) -> Vec2i {
+        let Rect{x, y, w, h} = self.hitbox.rect;
+        let (ax, ay) = self.sprite.anchor;
+        Vec2i(x + (ax * w as f32).round() as i32, y + (ay * h as f32).round() as i32)
     }
 }
 
 pub trait DrawEntityExt {
-    fn draw_entity(&mut self, s: &mut Entity);
+    fn draw_entity(&mut self, s: &Entity);
 }
 
 use crate::screen::Screen;
 impl<'fb> DrawEntityExt for Screen<'fb> {
-    fn draw_entity(&mut self, e: &mut Entity) {
-        e.sprite.position = Vec2i(e.hitbox.rect.x, e.hitbox.rect.y);
-        self.draw_sprite(&mut e.sprite);
+    fn draw_entity(&mut self, e: &Entity) {
+        let at = e.anchor_point();
+        let mut sprites = vec![(Transform::at(at), &e.sprite)];
+        // The parts go along with how the body is drawn, and hide with it
+        let s = &e.sprite;
+        let root = Transform {
+            offset: at,
+            rotation: s.rotation,
+            scale: s.scale,
+            flip_x: s.flip_x,
+            flip_y: s.flip_y,
+            opacity: s.opacity,
+            visible: s.visible,
+            z: s.z,
+        };
+        if root.visible {
+            for child in e.children.iter() {
+                child.collect(&root, &mut sprites);
+            }
+        }
+        self.draw_collected(sprites);
     }
 }
-
-pub struct Bird {
-    pub body: Entity,
-    pub wing: Sprite,
-}
-
-pub trait DrawBirdExt {
-    fn draw_bird(&mut self, s: &mut Bird);
-}
-
-impl<'fb> DrawBirdExt for Screen<'fb> {
-    fn draw_bird(&mut self, b: &mut Bird) {
-        self.draw_entity(&mut b.body);
-        let Vec2i(x,y) = b.body.sprite.position;
-        b.wing.position = Vec2i(x, y - 12);
-        self.draw_sprite(&mut b.wing);
-    }
-}
\ No newline at end of file
@@ -0,0 +1,139 @@
+use crate::texture::Texture;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Anything that can be loaded again in place while the game is running.
+/// It's reloaded through a shared reference, so every `Rc` holder sees the new version.
+pub trait Reload {
+    fn reload_from(&self, path: &Path) -> Result<(), String>;
+}
+
+impl Reload for Texture {
+    fn reload_from(&self, path: &Path) -> Result<(), String> {
+        Texture::reload_from(self, path)
+    }
+}
+
+/// Watches files (by polling modification times, so it works anywhere) and reloads
+/// whatever was registered for them when they change.
+/// Changes to files nobody registered, like sounds or level data, are handed back from `poll`
+/// for the game to deal with.
+pub struct HotReload {
+    dirs: Vec<PathBuf>,
+    modified: HashMap<PathBuf, SystemTime>,
+    targets: Vec<(PathBuf, Weak<dyn Reload>)>,
+    interval: Duration,
+    last_poll: Instant,
+}
+
+#[allow(dead_code)]
+impl HotReload {
+    pub fn new() -> Self {
+        Self {
+            dirs: vec![],
+            modified: HashMap::new(),
+            targets: vec![],
+            interval: Duration::from_millis(500),
+            last_poll: Instant::now(),
+        }
+    }
+    // How often poll actually looks at the disk; it's cheap to call every frame
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+    /// Watch every file under `dir`, including subdirectories.
+    pub fn watch_dir(&mut self, dir: &Path) {
+        self.dirs.push(dir.to_path_buf());
+        for file in files_under(dir) {
+            self.remember(&file);
+        }
+    }
+    /// Reload `target` whenever the file at `path` changes.
+    /// Only a weak reference is kept, so dropping the asset stops the watching.
+    pub fn watch<T: Reload + 'static>(&mut self, path: &Path, target: &Rc<T>) {
+        let path = canonical(path);
+        self.remember(&path);
+        let target = Rc::downgrade(target) as Weak<dyn Reload>;
+        self.targets.push((path, target));
+    }
+    /// Watch a texture that was loaded from a file.
+    pub fn watch_texture(&mut self, texture: &Rc<Texture>) {
+        let path = texture.source().expect("Texture wasn't loaded from a file").to_path_buf();
+        self.watch(&path, texture);
+    }
+    /// Check for changed files and reload what was registered for them.
+    /// Returns the (canonical) paths of every file that changed since the last poll.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        if self.last_poll.elapsed() < self.interval {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+        let mut files: Vec<PathBuf> = self.dirs.iter().flat_map(|dir| files_under(dir)).collect();
+        files.extend(self.targets.iter().map(|(path, _)| path.clone()));
+        files.sort();
+        files.dedup();
+        let mut changed = vec![];
+        for file in files {
+            let time = match modified_time(&file) {
+                Some(time) => time,
+                None => continue,
+            };
+            if self.modified.insert(file.clone(), time) != Some(time) {
+                changed.push(file);
+            }
+        }
+        // Assets that have been dropped don't need watching anymore
+        self.targets.retain(|(_, target)| target.upgrade().is_some());
+        for (path, target) in self.targets.iter() {
+            if !changed.contains(path) {
+                continue;
+            }
+            if let Some(target) = target.upgrade() {
+                if let Err(e) = target.reload_from(path) {
+                    eprintln!("couldn't reload {}: {}", path.display(), e);
+                }
+            }
+        }
+        changed
+    }
+
+    fn remember(&mut self, path: &Path) {
+        let path = canonical(path);
+        if let Some(time) = modified_time(&path) {
+            self.modified.insert(path, time);
+        }
+    }
+}
+
+impl Default for HotReload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Paths are compared after canonicalizing, so "./res/a.png" and "res/a.png" are the same file
+pub fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn files_under(dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                files.extend(files_under(&path));
+            } else {
+                files.push(canonical(&path));
+            }
+        }
+    }
+    files
+}
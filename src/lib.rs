@@ -1,11 +1,20 @@
 pub mod animation;
+pub mod animstate;
 pub mod collision;
 pub mod entity;
+pub mod hotreload;
+pub mod query;
+pub mod resample;
+pub mod scene;
 pub mod screen;
+pub mod shape;
+pub mod skeleton;
 pub mod sound;
 pub mod sprite;
 pub mod texture;
 pub mod tiles;
+pub mod timeline;
+pub mod tween;
 pub mod types;
 
 #[macro_use]
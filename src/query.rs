@@ -0,0 +1,356 @@
+use crate::collision::{ColliderID, Mobile, PixelMask, Wall, ALL_LAYERS};
+use crate::shape::{self, Shape};
+use crate::tiles::{TileCollision, Tilemap, TILE_SZ};
+use crate::types::Rect;
+
+/// What a ray or a cast shape ran into.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Target {
+    Collider(ColliderID),
+    Tile { col: i32, row: i32 },
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct RayHit {
+    // Where the ray got to; for a cast shape, where its rect's top left got to
+    pub point: (f32, f32),
+    // Facing out of what got hit
+    pub normal: (f32, f32),
+    pub target: Target,
+    // In pixels along the ray
+    pub distance: f32,
+}
+
+/// Which colliders a query sees: ones on a layer in `mask`, triggers only if asked for,
+/// and never `ignore` (say, whoever's looking).
+#[derive(Clone, Copy, Debug)]
+pub struct Filter {
+    pub mask: u32,
+    pub triggers: bool,
+    pub ignore: Option<ColliderID>,
+}
+
+#[allow(dead_code)]
+impl Filter {
+    pub fn new() -> Self {
+        Self { mask: ALL_LAYERS, triggers: false, ignore: None }
+    }
+    pub fn with_mask(mut self, mask: u32) -> Self {
+        self.mask = mask;
+        self
+    }
+    pub fn with_triggers(mut self) -> Self {
+        self.triggers = true;
+        self
+    }
+    pub fn ignoring(mut self, id: ColliderID) -> Self {
+        self.ignore = Some(id);
+        self
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A collider with what the narrowphase needs
+type Candidate<'a> = (ColliderID, Rect, &'a Shape, &'a Option<PixelMask>);
+
+// Every collider the filter lets through
+fn colliders<'a>(statics: &'a [Wall], dynamics: &'a [Mobile], filter: Filter) -> impl Iterator<Item = Candidate<'a>> {
+    let walls = statics.iter().enumerate().map(|(i, w)| (ColliderID::Static(i), w.rect, &w.shape, &w.pixels, w.layer, w.trigger));
+    let mobiles = dynamics.iter().enumerate().map(|(i, m)| (ColliderID::Dynamic(i), m.rect, &m.shape, &m.pixels, m.layer, m.trigger));
+    walls
+        .chain(mobiles)
+        .filter(move |(id, _, _, _, layer, trigger)| layer & filter.mask != 0 && (filter.triggers || !trigger) && filter.ignore != Some(*id))
+        .map(|(id, rect, shape, pixels, _, _)| (id, rect, shape, pixels))
+}
+
+// `to - from` as a unit vector and a length, or None if they're the same point
+fn direction(from: (f32, f32), to: (f32, f32)) -> Option<((f32, f32), f32)> {
+    let (x, y) = (to.0 - from.0, to.1 - from.1);
+    let length = (x * x + y * y).sqrt();
+    if length < 1e-6 {
+        return None;
+    }
+    Some(((x / length, y / length), length))
+}
+
+fn along(from: (f32, f32), dir: (f32, f32), t: f32) -> (f32, f32) {
+    (from.0 + dir.0 * t, from.1 + dir.1 * t)
+}
+
+/// The first collider a ray from `from` along `dir` meets within `max` pixels.
+/// `dir` doesn't need to be a unit vector. Goes by shapes, not pixel masks.
+pub fn raycast(statics: &[Wall], dynamics: &[Mobile], from: (f32, f32), dir: (f32, f32), max: f32, filter: Filter) -> Option<RayHit> {
+    let (dir, _) = direction((0.0, 0.0), dir)?;
+    colliders(statics, dynamics, filter)
+        .filter_map(|(id, rect, shape, _)| shape::raycast(shape, rect, from, dir, max).map(|(t, normal)| (id, t, normal)))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(id, t, normal)| RayHit { point: along(from, dir, t), normal, target: Target::Collider(id), distance: t })
+}
+
+/// The first collider between `from` and `to`, e.g. for line of sight.
+pub fn segment_cast(statics: &[Wall], dynamics: &[Mobile], from: (f32, f32), to: (f32, f32), filter: Filter) -> Option<RayHit> {
+    let (dir, length) = direction(from, to)?;
+    raycast(statics, dynamics, from, dir, length, filter)
+}
+
+/// Like `raycast`, but moving `shape` on `rect` instead of a point, e.g. to see if a mobile fits
+/// somewhere before moving it there.
+pub fn shapecast(statics: &[Wall], dynamics: &[Mobile], shape: &Shape, rect: Rect, dir: (f32, f32), max: f32, filter: Filter) -> Option<RayHit> {
+    let (dir, _) = direction((0.0, 0.0), dir)?;
+    colliders(statics, dynamics, filter)
+        .filter_map(|(id, r, s, _)| shape::shapecast(shape, rect, dir, max, s, r).map(|(t, normal)| (id, t, normal)))
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(id, t, normal)| RayHit { point: along((rect.x as f32, rect.y as f32), dir, t), normal, target: Target::Collider(id), distance: t })
+}
+
+/// Colliders under the point `p`, like for mouse picking. Unlike the other queries, colliders
+/// with pixel masks only count where their pixels are solid.
+pub fn overlap_point(statics: &[Wall], dynamics: &[Mobile], p: (f32, f32), filter: Filter) -> Vec<ColliderID> {
+    colliders(statics, dynamics, filter)
+        .filter(|(_, rect, shape, pixels)| {
+            shape::contains(shape, *rect, p)
+                && pixels.as_ref().map_or(true, |m| {
+                    let (x, y) = (p.0.floor() as i32 - rect.x - m.offset.0, p.1.floor() as i32 - rect.y - m.offset.1);
+                    m.mask.get(x, y)
+                })
+        })
+        .map(|(id, _, _, _)| id)
+        .collect()
+}
+
+/// Colliders touching the rect `r`.
+pub fn overlap_rect(statics: &[Wall], dynamics: &[Mobile], r: Rect, filter: Filter) -> Vec<ColliderID> {
+    overlap_shape(statics, dynamics, &Shape::Rect, r, filter)
+}
+
+/// Colliders touching the circle around `center`.
+pub fn overlap_circle(statics: &[Wall], dynamics: &[Mobile], center: (f32, f32), radius: f32, filter: Filter) -> Vec<ColliderID> {
+    let (shape, rect) = circle(center, radius);
+    overlap_shape(statics, dynamics, &shape, rect, filter)
+}
+
+fn overlap_shape(statics: &[Wall], dynamics: &[Mobile], shape: &Shape, rect: Rect, filter: Filter) -> Vec<ColliderID> {
+    colliders(statics, dynamics, filter)
+        .filter(|(_, r, s, _)| shape::overlap(shape, rect, s, *r).is_some())
+        .map(|(id, _, _, _)| id)
+        .collect()
+}
+
+// A circle shape and a rect around it, since shapes are placed on rects
+fn circle(center: (f32, f32), radius: f32) -> (Shape, Rect) {
+    let (x, y) = ((center.0 - radius).floor() as i32, (center.1 - radius).floor() as i32);
+    let size = (radius * 2.0).ceil() as u16 + 1;
+    let shape = Shape::Circle { center: (center.0 - x as f32, center.1 - y as f32), radius };
+    (shape, Rect { x, y, w: size, h: size })
+}
+
+fn on_map(map: &Tilemap, col: i32, row: i32) -> bool {
+    col >= 0 && row >= 0 && col < map.dims.0 as i32 && row < map.dims.1 as i32
+}
+
+// What a tile looks like to a query, or None if it's a flat slope with nothing to it
+fn tile_shape(kind: TileCollision) -> Option<Shape> {
+    let t = TILE_SZ as f32;
+    match kind {
+        TileCollision::Slope { left: 0, right: 0 } => None,
+        TileCollision::Slope { left, right } => Some(Shape::Polygon(vec![(0.0, t - left as f32), (t, t - right as f32), (t, t), (0.0, t)])),
+        _ => Some(Shape::Rect),
+    }
+}
+
+// One-way tiles only get hit on their top, from above
+fn tile_hit(kind: TileCollision, hit: Option<(f32, (f32, f32))>) -> Option<(f32, (f32, f32))> {
+    match (kind, hit) {
+        (TileCollision::OneWay, Some((t, normal))) if t <= 0.0 || normal != (0.0, -1.0) => None,
+        (_, hit) => hit,
+    }
+}
+
+/// The first tile of a kind `keep` likes that a ray from `from` along `dir` meets within
+/// `max` pixels, stepping through the grid a tile at a time.
+pub fn raycast_tiles(map: &Tilemap, from: (f32, f32), dir: (f32, f32), max: f32, keep: impl Fn(TileCollision) -> bool) -> Option<RayHit> {
+    let (dir, _) = direction((0.0, 0.0), dir)?;
+    let size = TILE_SZ as f32;
+    let (x, y) = (from.0 - map.position.0 as f32, from.1 - map.position.1 as f32);
+    let (mut col, mut row) = ((x / size).floor() as i32, (y / size).floor() as i32);
+    // How far along the ray the next column and row start, and how far apart they are
+    let sign = |d: f32| if d > 0.0 { 1 } else if d < 0.0 { -1 } else { 0 };
+    let step = (sign(dir.0), sign(dir.1));
+    let next = |at: f32, cell: i32, d: f32| match d {
+        d if d > 0.0 => ((cell + 1) as f32 * size - at) / d,
+        d if d < 0.0 => (cell as f32 * size - at) / d,
+        _ => f32::INFINITY,
+    };
+    let (mut next_col, mut next_row) = (next(x, col, dir.0), next(y, row, dir.1));
+    let gap = (size / dir.0.abs(), size / dir.1.abs());
+    let (cols, rows) = (map.dims.0 as i32, map.dims.1 as i32);
+    loop {
+        let kind = map.collision_at(col, row);
+        if on_map(map, col, row) && keep(kind) {
+            let hit = tile_shape(kind).and_then(|shape| shape::raycast(&shape, map.cell_rect(col, row), from, dir, max));
+            if let Some((t, normal)) = tile_hit(kind, hit) {
+                return Some(RayHit { point: along(from, dir, t), normal, target: Target::Tile { col, row }, distance: t });
+            }
+        }
+        // Off the map and heading further away
+        if (col < 0 && step.0 <= 0) || (col >= cols && step.0 >= 0) || (row < 0 && step.1 <= 0) || (row >= rows && step.1 >= 0) {
+            return None;
+        }
+        if next_col < next_row {
+            if next_col > max {
+                return None;
+            }
+            col += step.0;
+            next_col += gap.0;
+        } else {
+            if next_row > max {
+                return None;
+            }
+            row += step.1;
+            next_row += gap.1;
+        }
+    }
+}
+
+/// The first tile `keep` likes between `from` and `to`.
+pub fn segment_cast_tiles(map: &Tilemap, from: (f32, f32), to: (f32, f32), keep: impl Fn(TileCollision) -> bool) -> Option<RayHit> {
+    let (dir, length) = direction(from, to)?;
+    raycast_tiles(map, from, dir, length, keep)
+}
+
+/// Like `raycast_tiles`, but moving `shape` on `rect`. Looks at every tile the shape could
+/// pass over, so keep `max` to a few tiles.
+pub fn shapecast_tiles(map: &Tilemap, shape: &Shape, rect: Rect, dir: (f32, f32), max: f32, keep: impl Fn(TileCollision) -> bool) -> Option<RayHit> {
+    let (dir, _) = direction((0.0, 0.0), dir)?;
+    let far = along((rect.x as f32, rect.y as f32), dir, max);
+    let (x0, y0) = (rect.x.min(far.0.floor() as i32), rect.y.min(far.1.floor() as i32));
+    let (x1, y1) = ((rect.x + rect.w as i32).max(far.0.ceil() as i32 + rect.w as i32), (rect.y + rect.h as i32).max(far.1.ceil() as i32 + rect.h as i32));
+    let swept = Rect { x: x0, y: y0, w: (x1 - x0) as u16, h: (y1 - y0) as u16 };
+    let (cols, rows) = map.cells_under(swept);
+    let mut best: Option<RayHit> = None;
+    for row in rows {
+        for col in cols.clone() {
+            let kind = map.collision_at(col, row);
+            if !on_map(map, col, row) || !keep(kind) {
+                continue;
+            }
+            let hit = tile_shape(kind).and_then(|s| shape::shapecast(shape, rect, dir, max, &s, map.cell_rect(col, row)));
+            if let Some((t, normal)) = tile_hit(kind, hit) {
+                if best.map_or(true, |b| t < b.distance) {
+                    let point = along((rect.x as f32, rect.y as f32), dir, t);
+                    best = Some(RayHit { point, normal, target: Target::Tile { col, row }, distance: t });
+                }
+            }
+        }
+    }
+    best
+}
+
+/// The tile under the point `p`, if it's on the map and `keep` likes it; slopes only count
+/// below their floor. Picking a tile with the mouse is `overlap_point_tiles(map, mouse, |_| true)`.
+pub fn overlap_point_tiles(map: &Tilemap, p: (f32, f32), keep: impl Fn(TileCollision) -> bool) -> Option<(i32, i32)> {
+    let size = TILE_SZ as f32;
+    let col = ((p.0 - map.position.0 as f32) / size).floor() as i32;
+    let row = ((p.1 - map.position.1 as f32) / size).floor() as i32;
+    let kind = map.collision_at(col, row);
+    let inside = match kind {
+        TileCollision::Slope { .. } => tile_shape(kind).is_some_and(|s| shape::contains(&s, map.cell_rect(col, row), p)),
+        _ => true,
+    };
+    if on_map(map, col, row) && keep(kind) && inside {
+        Some((col, row))
+    } else {
+        None
+    }
+}
+
+/// Tiles `keep` likes that touch the rect `r`.
+pub fn overlap_rect_tiles(map: &Tilemap, r: Rect, keep: impl Fn(TileCollision) -> bool) -> Vec<(i32, i32)> {
+    overlap_shape_tiles(map, &Shape::Rect, r, keep)
+}
+
+/// Tiles `keep` likes that touch the circle around `center`.
+pub fn overlap_circle_tiles(map: &Tilemap, center: (f32, f32), radius: f32, keep: impl Fn(TileCollision) -> bool) -> Vec<(i32, i32)> {
+    let (shape, rect) = circle(center, radius);
+    overlap_shape_tiles(map, &shape, rect, keep)
+}
+
+fn overlap_shape_tiles(map: &Tilemap, shape: &Shape, rect: Rect, keep: impl Fn(TileCollision) -> bool) -> Vec<(i32, i32)> {
+    let (cols, rows) = map.cells_under(rect);
+    let mut tiles = vec![];
+    for row in rows {
+        for col in cols.clone() {
+            let kind = map.collision_at(col, row);
+            if !on_map(map, col, row) || !keep(kind) {
+                continue;
+            }
+            if tile_shape(kind).is_some_and(|s| shape::overlap(shape, rect, &s, map.cell_rect(col, row)).is_some()) {
+                tiles.push((col, row));
+            }
+        }
+    }
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::{PixelFormat, Texture};
+    use crate::tiles::{Tile, Tileset};
+    use crate::types::Vec2i;
+    use std::rc::Rc;
+
+    // 4x4 tiles, all empty but the ones given
+    fn map(tiles: &[((i32, i32), TileCollision)]) -> Tilemap {
+        let texture = Rc::new(Texture::with_format(vec![0; 64 * 16 * 4], 64, 16, PixelFormat::Rgba8));
+        let kinds = [TileCollision::Empty, TileCollision::Solid, TileCollision::OneWay];
+        let tileset = Rc::new(Tileset::new(
+            kinds.iter().map(|&collision| Tile { oppgrid: false, opphit: false, myship: false, collision }).collect(),
+            &texture,
+        ));
+        let mut ids = vec![0; 16];
+        for ((col, row), kind) in tiles {
+            ids[(row * 4 + col) as usize] = kinds.iter().position(|k| k == kind).unwrap();
+        }
+        Tilemap::new(Vec2i(0, 0), (4, 4), &tileset, ids)
+    }
+
+    #[test]
+    fn raycast_tiles_steps_through_corners() {
+        // Straight through the corners at (16, 16) and (32, 32)
+        let map = map(&[((2, 2), TileCollision::Solid)]);
+        let d = std::f32::consts::FRAC_1_SQRT_2;
+        let hit = raycast_tiles(&map, (8.0, 8.0), (d, d), 100.0, |k| k != TileCollision::Empty).unwrap();
+        assert_eq!(hit.target, Target::Tile { col: 2, row: 2 });
+        assert!((hit.distance - 24.0 * std::f32::consts::SQRT_2).abs() < 1e-3);
+        // Stopping just short of it
+        assert!(raycast_tiles(&map, (8.0, 8.0), (d, d), 33.0, |k| k != TileCollision::Empty).is_none());
+        // Going the other way from the far corner
+        let hit = raycast_tiles(&map, (56.0, 56.0), (-d, -d), 100.0, |k| k == TileCollision::Solid).unwrap();
+        assert_eq!(hit.target, Target::Tile { col: 2, row: 2 });
+        assert!((hit.distance - 8.0 * std::f32::consts::SQRT_2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn one_way_tiles_only_stop_rays_from_above() {
+        let map = map(&[((1, 2), TileCollision::OneWay)]);
+        let hit = raycast_tiles(&map, (24.0, 0.0), (0.0, 1.0), 100.0, |k| k != TileCollision::Empty).unwrap();
+        assert_eq!(hit.target, Target::Tile { col: 1, row: 2 });
+        assert_eq!(hit.normal, (0.0, -1.0));
+        assert_eq!(hit.distance, 32.0);
+        // From below, from the side, and from inside it goes straight through
+        assert!(raycast_tiles(&map, (24.0, 60.0), (0.0, -1.0), 100.0, |k| k != TileCollision::Empty).is_none());
+        assert!(raycast_tiles(&map, (0.0, 40.0), (1.0, 0.0), 100.0, |k| k != TileCollision::Empty).is_none());
+        assert!(raycast_tiles(&map, (24.0, 40.0), (0.0, 1.0), 100.0, |k| k != TileCollision::Empty).is_none());
+        // Shape casts go by the same rule
+        let rect = Rect { x: 20, y: 0, w: 8, h: 8 };
+        assert!(shapecast_tiles(&map, &Shape::Rect, rect, (0.0, 1.0), 40.0, |k| k != TileCollision::Empty).is_some());
+        let rect = Rect { x: 0, y: 36, w: 8, h: 8 };
+        assert!(shapecast_tiles(&map, &Shape::Rect, rect, (1.0, 0.0), 40.0, |k| k != TileCollision::Empty).is_none());
+    }
+}
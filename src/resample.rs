@@ -0,0 +1,181 @@
+use crate::texture::{PixelFormat, Texture};
+
+/// How to fill in pixels when a texture changes size.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Filter {
+    /// Copy the closest source pixel; keeps the format (and palette) as is
+    Nearest,
+    /// Blend the four closest source pixels
+    Bilinear,
+    /// Average every source pixel the destination pixel covers, best for shrinking
+    Box,
+    /// Scale2x/EPX doubling, which keeps pixel art edges crisp; the last step to an
+    /// exact size is nearest neighbor
+    Epx,
+}
+
+// Resampling is done at load time, so none of this has to be fast.
+#[allow(dead_code)]
+impl Texture {
+    /// Make a new texture of the given size from this one.
+    /// Frames into the old texture need to be scaled by the same factor to line up again.
+    pub fn resize(&self, width: usize, height: usize, filter: Filter) -> Texture {
+        assert!(width > 0 && height > 0, "Can't resize a texture to nothing");
+        if self.width() == 0 || self.height() == 0 {
+            // Nothing to sample, so it's all transparent
+            return self.with_image(vec![0; width * height * self.depth()], width, height);
+        }
+        match filter {
+            Filter::Nearest => self.nearest(width, height),
+            Filter::Bilinear => self.bilinear(width, height),
+            Filter::Box => self.area_average(width, height),
+            Filter::Epx => {
+                let mut scaled = self.convert(self.format());
+                while scaled.width() * 2 <= width && scaled.height() * 2 <= height {
+                    scaled = scaled.scale2x();
+                }
+                scaled.nearest(width, height)
+            }
+        }
+    }
+    /// Multiply the size by `factor`, e.g. 2.0 to build `bird_big` from `bird`.
+    pub fn scale(&self, factor: f32, filter: Filter) -> Texture {
+        let width = ((self.width() as f32 * factor).round() as usize).max(1);
+        let height = ((self.height() as f32 * factor).round() as usize).max(1);
+        self.resize(width, height, filter)
+    }
+    /// Successively halved copies of this texture down to 1x1, not including itself.
+    pub fn mipmaps(&self) -> Vec<Texture> {
+        let mut levels: Vec<Texture> = vec![];
+        let (mut w, mut h) = self.size();
+        while w > 1 || h > 1 {
+            w = (w / 2).max(1);
+            h = (h / 2).max(1);
+            let level = levels.last().unwrap_or(self).area_average(w, h);
+            levels.push(level);
+        }
+        levels
+    }
+    /// Double the size with the Scale2x (EPX) rule: each pixel becomes a 2x2 block,
+    /// and corners take a neighbor's color when two neighbors agree on an edge.
+    pub fn scale2x(&self) -> Texture {
+        let depth = self.depth();
+        let (w, h) = self.size();
+        if w == 0 || h == 0 {
+            return self.with_image(vec![], w * 2, h * 2);
+        }
+        let buffer = self.buffer();
+        let mut image = vec![0; w * h * 4 * depth];
+        let px = |x: usize, y: usize| &buffer[(y * w + x) * depth..(y * w + x + 1) * depth];
+        let out_pitch = w * 2 * depth;
+        for y in 0..h {
+            for x in 0..w {
+                let p = px(x, y);
+                let a = px(x, y.saturating_sub(1));
+                let b = px((x + 1).min(w - 1), y);
+                let c = px(x.saturating_sub(1), y);
+                let d = px(x, (y + 1).min(h - 1));
+                let mut out = [p, p, p, p];
+                if c == a && c != d && a != b {
+                    out[0] = a;
+                }
+                if a == b && a != c && b != d {
+                    out[1] = b;
+                }
+                if d == c && d != b && c != a {
+                    out[2] = c;
+                }
+                if b == d && b != a && d != c {
+                    out[3] = d;
+                }
+                for (i, src) in out.iter().enumerate() {
+                    let idx = (y * 2 + i / 2) * out_pitch + (x * 2 + i % 2) * depth;
+                    image[idx..idx + depth].copy_from_slice(src);
+                }
+            }
+        }
+        self.with_image(image, w * 2, h * 2)
+    }
+
+    // Same format and palette, new pixels
+    fn with_image(&self, image: Vec<u8>, width: usize, height: usize) -> Texture {
+        match self.palette() {
+            Some(palette) => Texture::with_palette(image, width, height, palette.clone()),
+            None => Texture::with_format(image, width, height, self.format()),
+        }
+    }
+    fn nearest(&self, width: usize, height: usize) -> Texture {
+        let depth = self.depth();
+        let (sw, sh) = self.size();
+        let buffer = self.buffer();
+        let mut image = Vec::with_capacity(width * height * depth);
+        for y in 0..height {
+            // Sample at pixel centers so both edges get the same treatment
+            let sy = ((2 * y + 1) * sh) / (2 * height);
+            for x in 0..width {
+                let sx = ((2 * x + 1) * sw) / (2 * width);
+                let idx = (sy * sw + sx) * depth;
+                image.extend_from_slice(&buffer[idx..idx + depth]);
+            }
+        }
+        self.with_image(image, width, height)
+    }
+    // Blending has to happen on premultiplied colors or transparent pixels bleed dark fringes
+    fn bilinear(&self, width: usize, height: usize) -> Texture {
+        let src = self.convert(PixelFormat::Rgba8Premul);
+        let (sw, sh) = src.size();
+        let buffer = src.buffer();
+        let at = |x: usize, y: usize, c: usize| buffer[(y * sw + x) * 4 + c] as f32;
+        let mut image = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let fy = ((y as f32 + 0.5) * sh as f32 / height as f32 - 0.5).max(0.0);
+            let y0 = (fy as usize).min(sh - 1);
+            let y1 = (y0 + 1).min(sh - 1);
+            let ty = fy - y0 as f32;
+            for x in 0..width {
+                let fx = ((x as f32 + 0.5) * sw as f32 / width as f32 - 0.5).max(0.0);
+                let x0 = (fx as usize).min(sw - 1);
+                let x1 = (x0 + 1).min(sw - 1);
+                let tx = fx - x0 as f32;
+                for c in 0..4 {
+                    let top = at(x0, y0, c) * (1.0 - tx) + at(x1, y0, c) * tx;
+                    let bot = at(x0, y1, c) * (1.0 - tx) + at(x1, y1, c) * tx;
+                    image.push((top * (1.0 - ty) + bot * ty).round() as u8);
+                }
+            }
+        }
+        Texture::with_format(image, width, height, PixelFormat::Rgba8Premul)
+    }
+    fn area_average(&self, width: usize, height: usize) -> Texture {
+        let src = self.convert(PixelFormat::Rgba8Premul);
+        let (sw, sh) = src.size();
+        let buffer = src.buffer();
+        let x_scale = sw as f32 / width as f32;
+        let y_scale = sh as f32 / height as f32;
+        let mut image = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let (y0, y1) = (y as f32 * y_scale, (y + 1) as f32 * y_scale);
+            for x in 0..width {
+                let (x0, x1) = (x as f32 * x_scale, (x + 1) as f32 * x_scale);
+                let mut sum = [0.0f32; 4];
+                let mut total = 0.0;
+                // Weight each source pixel by how much of it falls inside this destination pixel
+                for sy in (y0 as usize)..(y1.ceil() as usize).min(sh) {
+                    let wy = y1.min(sy as f32 + 1.0) - y0.max(sy as f32);
+                    for sx in (x0 as usize)..(x1.ceil() as usize).min(sw) {
+                        let wx = x1.min(sx as f32 + 1.0) - x0.max(sx as f32);
+                        let idx = (sy * sw + sx) * 4;
+                        for (s, c) in sum.iter_mut().zip(&buffer[idx..idx + 4]) {
+                            *s += *c as f32 * wx * wy;
+                        }
+                        total += wx * wy;
+                    }
+                }
+                for c in sum.iter() {
+                    image.push((c / total).round() as u8);
+                }
+            }
+        }
+        Texture::with_format(image, width, height, PixelFormat::Rgba8Premul)
+    }
+}
@@ -0,0 +1,154 @@
+use crate::screen::Screen;
+use crate::sprite::{DrawSpriteExt, Sprite};
+use crate::types::Vec2i;
+
+/// Where a node sits relative to its parent, and how it looks.
+/// Children inherit all of it: they move, turn, scale, flip, fade and hide along with their parent.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Transform {
+    pub offset: Vec2i,
+    // Degrees clockwise, around the node's own spot (the sprite's pivot)
+    pub rotation: f32,
+    pub scale: f32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub opacity: f32,
+    pub visible: bool,
+    // Added to the parent's z; higher is drawn on top
+    pub z: i32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            offset: Vec2i(0, 0),
+            rotation: 0.0,
+            scale: 1.0,
+            flip_x: false,
+            flip_y: false,
+            opacity: 1.0,
+            visible: true,
+            z: 0,
+        }
+    }
+}
+
+impl Transform {
+    pub fn at(offset: Vec2i) -> Self {
+        Self { offset, ..Self::default() }
+    }
+    /// Turn `child`, which is relative to this transform, into a transform relative to whatever this one is.
+    pub fn then(&self, child: &Transform) -> Transform {
+        let Vec2i(x, y) = child.offset;
+        let (mut x, mut y) = (x as f32 * self.scale, y as f32 * self.scale);
+        // A flipped parent mirrors where its children are, too, and which way they turn
+        if self.flip_x {
+            x = -x;
+        }
+        if self.flip_y {
+            y = -y;
+        }
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let mirrored = self.flip_x != self.flip_y;
+        Transform {
+            offset: Vec2i(
+                self.offset.0 + (x * cos - y * sin).round() as i32,
+                self.offset.1 + (x * sin + y * cos).round() as i32,
+            ),
+            rotation: self.rotation + if mirrored { -child.rotation } else { child.rotation },
+            scale: self.scale * child.scale,
+            flip_x: self.flip_x != child.flip_x,
+            flip_y: self.flip_y != child.flip_y,
+            opacity: self.opacity * child.opacity,
+            visible: self.visible && child.visible,
+            z: self.z + child.z,
+        }
+    }
+}
+
+/// A part of a composite character, like a bird's wing: maybe a sprite, placed relative to
+/// its parent, with parts of its own.
+pub struct Node {
+    pub name: String,
+    pub sprite: Option<Sprite>,
+    pub transform: Transform,
+    pub children: Vec<Node>,
+}
+
+#[allow(dead_code)]
+impl Node {
+    // A node with nothing to draw, for grouping
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            sprite: None,
+            transform: Transform::default(),
+            children: vec![],
+        }
+    }
+    pub fn with_sprite(name: &str, sprite: Sprite) -> Self {
+        Self { sprite: Some(sprite), ..Self::new(name) }
+    }
+    pub fn add_child(&mut self, child: Node) {
+        self.children.push(child);
+    }
+    /// This node or the first one below it with the given name.
+    pub fn find(&self, name: &str) -> Option<&Node> {
+        if self.name == name {
+            return Some(self);
+        }
+        self.children.iter().find_map(|c| c.find(name))
+    }
+    pub fn find_mut(&mut self, name: &str) -> Option<&mut Node> {
+        if self.name == name {
+            return Some(self);
+        }
+        self.children.iter_mut().find_map(|c| c.find_mut(name))
+    }
+    pub fn advance(&mut self, dt: f64) {
+        if let Some(sprite) = self.sprite.as_mut() {
+            sprite.advance(dt);
+        }
+        for child in self.children.iter_mut() {
+            child.advance(dt);
+        }
+    }
+    // This node's frame events, then its children's
+    pub fn take_events(&mut self) -> Vec<String> {
+        let mut events = self.sprite.as_mut().map(|s| s.take_events()).unwrap_or_default();
+        for child in self.children.iter_mut() {
+            events.extend(child.take_events());
+        }
+        events
+    }
+    // Every sprite in the tree with its final transform, parents before children
+    pub(crate) fn collect<'a>(&'a self, parent: &Transform, out: &mut Vec<(Transform, &'a Sprite)>) {
+        let world = parent.then(&self.transform);
+        if let Some(sprite) = self.sprite.as_ref() {
+            out.push((world, sprite));
+        }
+        for child in self.children.iter() {
+            child.collect(&world, out);
+        }
+    }
+}
+
+pub trait DrawNodeExt {
+    fn draw_node(&mut self, node: &Node, at: Vec2i);
+    // Draw sprites with their transforms, sorted by z; same z keeps the order they came in
+    fn draw_collected(&mut self, sprites: Vec<(Transform, &Sprite)>);
+}
+
+impl<'fb> DrawNodeExt for Screen<'fb> {
+    fn draw_node(&mut self, node: &Node, at: Vec2i) {
+        let mut sprites = vec![];
+        node.collect(&Transform::at(at), &mut sprites);
+        self.draw_collected(sprites);
+    }
+    fn draw_collected(&mut self, mut sprites: Vec<(Transform, &Sprite)>) {
+        sprites.sort_by_key(|(t, s)| t.z + s.z);
+        for (t, s) in sprites {
+            self.draw_sprite_transformed(s, &t);
+        }
+    }
+}
@@ -1,6 +1,28 @@
 // We can pull in definitions from elsewhere in the crate!
-use crate::texture::Texture;
+use crate::texture::{premultiply, Palette, PixelFormat, Texture};
 use crate::types::{Rect, Rgba, Vec2i};
+
+/// Ways to change how a texture gets drawn; the default draws it as it is.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct BlitOptions {
+    pub flip_x: bool,
+    pub flip_y: bool,
+    // Nearest neighbor, so whole numbers look best for pixel art
+    pub scale: f32,
+    // 0.0 is invisible, 1.0 is the texture's own alpha
+    pub opacity: f32,
+    // Degrees clockwise, turning around `origin`
+    pub rotation: f32,
+    // In screen pixels from the top left of the (scaled and flipped) picture
+    pub origin: Vec2i,
+}
+
+impl Default for BlitOptions {
+    fn default() -> Self {
+        Self { flip_x: false, flip_y: false, scale: 1.0, opacity: 1.0, rotation: 0.0, origin: Vec2i(0, 0) }
+    }
+}
+
 pub struct Screen<'fb> {
     framebuffer: &'fb mut [u8],
     width: usize,
@@ -113,10 +135,163 @@ impl<'fb> Screen<'fb> {
         self.line(Vec2i(r.x, r.y), Vec2i(r.x + r.w as i32, r.y), col);
         self.line(Vec2i(r.x, r.y + r.h as i32), Vec2i(r.x + r.w as i32, r.y + r.h as i32), col);
         self.line(Vec2i(r.x, r.y), Vec2i(r.x, r.y + r.h as i32), col);
-        self.line(Vec2i(r.x + r.w as i32, r.y as i32), Vec2i(r.x + r.w as i32, r.y + r.h as i32), col);
+        self.line(Vec2i(r.x + r.w as i32, r.y), Vec2i(r.x + r.w as i32, r.y + r.h as i32), col);
     }
     // Bitblt too begins with a translation
-    pub fn bitblt(&mut self, src:&Texture, from: Rect, Vec2i(to_x, to_y): Vec2i) {
+    // The source can be in any pixel format; it's converted to premultiplied rgba as we go
+    pub fn bitblt(&mut self, src:&Texture, from: Rect, to: Vec2i) {
+        if let Some(palette) = src.palette() {
+            self.bitblt_palette(src, from, to, &palette);
+            return;
+        }
+        let format = src.format();
+        self.blit_with(src, from, to, |px| format.read_premul(px));
+    }
+    // Draw an indexed texture with some other palette than its own, e.g. for team colors
+    pub fn bitblt_palette(&mut self, src:&Texture, from: Rect, to: Vec2i, palette: &Palette) {
+        assert_eq!(src.format(), PixelFormat::Indexed8, "Only indexed textures can be drawn with a palette");
+        let lut = palette.premultiplied();
+        self.blit_with(src, from, to, |px| lut[px[0] as usize]);
+    }
+    // Draw a texture as a mask: its alpha (or luminance, for formats without alpha) says
+    // how much of =col= to put down.  Font glyphs can go straight from the rasterizer to here.
+    pub fn bitblt_mask(&mut self, src:&Texture, from: Rect, to: Vec2i, col: Rgba) {
+        let format = src.format();
+        let Rgba(r, g, b, a) = premultiply(col);
+        let scale = |c: u8, coverage: u8| ((c as u32 * coverage as u32 + 127) / 255) as u8;
+        self.blit_with(src, from, to, |px| {
+            let coverage = format.coverage(px);
+            Rgba(scale(r, coverage), scale(g, coverage), scale(b, coverage), scale(a, coverage))
+        });
+    }
+    // Flipped, scaled, rotated and/or faded version of bitblt
+    pub fn bitblt_with(&mut self, src:&Texture, from: Rect, to: Vec2i, opts: BlitOptions) {
+        if let Some(palette) = src.palette() {
+            self.bitblt_palette_with(src, from, to, &palette, opts);
+            return;
+        }
+        let format = src.format();
+        self.blit_transformed(src, from, to, opts, |px| format.read_premul(px));
+    }
+    pub fn bitblt_palette_with(&mut self, src:&Texture, from: Rect, to: Vec2i, palette: &Palette, opts: BlitOptions) {
+        assert_eq!(src.format(), PixelFormat::Indexed8, "Only indexed textures can be drawn with a palette");
+        let lut = palette.premultiplied();
+        self.blit_transformed(src, from, to, opts, |px| lut[px[0] as usize]);
+    }
+    fn blit_transformed(&mut self, src:&Texture, from: Rect, to: Vec2i, opts: BlitOptions, shade: impl Fn(&[u8]) -> Rgba) {
+        // Scaling and rotating read all over `from`, so all of it has to be in the texture
+        let (tw,th) = src.size();
+        assert!(0 <= from.x);
+        assert!(from.x + from.w as i32 <= tw as i32);
+        assert!(0 <= from.y);
+        assert!(from.y + from.h as i32 <= th as i32);
+        if opts == (BlitOptions { origin: opts.origin, ..BlitOptions::default() }) {
+            self.blit_with(src, from, to, shade);
+            return;
+        }
+        let opacity = opts.opacity.clamp(0.0, 1.0);
+        if opacity == 0.0 || opts.scale <= 0.0 {
+            return;
+        }
+        let fade = |c: u8| (c as f32 * opacity).round() as u8;
+        // Size on screen
+        let w = (from.w as f32 * opts.scale).round() as i32;
+        let h = (from.h as f32 * opts.scale).round() as i32;
+        if opts.rotation % 360.0 != 0.0 {
+            self.blit_rotated(src, from, to, opts, w, h, |px| {
+                let Rgba(r, g, b, a) = shade(px);
+                Rgba(fade(r), fade(g), fade(b), fade(a))
+            });
+            return;
+        }
+        let to_x = to.0 - self.position.0;
+        let to_y = to.1 - self.position.1;
+        // Only visit the part that lands on the screen
+        let x0 = to_x.max(0);
+        let x1 = (to_x + w).min(self.width as i32);
+        let y0 = to_y.max(0);
+        let y1 = (to_y + h).min(self.height as i32);
+        let depth = self.depth;
+        assert_eq!(depth, 4);
+        let src_depth = src.depth();
+        let src_pitch = src.pitch();
+        let dst_pitch = self.width * depth;
+        let src_buf = src.buffer();
+        for y in y0..y1 {
+            // Which source row this screen row samples, counted from the top of `from`
+            let mut sy = ((y - to_y) as i64 * from.h as i64 / h as i64) as i32;
+            if opts.flip_y {
+                sy = from.h as i32 - 1 - sy;
+            }
+            let src_row = (from.y + sy) as usize * src_pitch;
+            for x in x0..x1 {
+                let mut sx = ((x - to_x) as i64 * from.w as i64 / w as i64) as i32;
+                if opts.flip_x {
+                    sx = from.w as i32 - 1 - sx;
+                }
+                let idx = src_row + (from.x + sx) as usize * src_depth;
+                let Rgba(r, g, b, a) = shade(&src_buf[idx..idx + src_depth]);
+                let dst = y as usize * dst_pitch + x as usize * depth;
+                over(&mut self.framebuffer[dst..dst + depth], Rgba(fade(r), fade(g), fade(b), fade(a)));
+            }
+        }
+    }
+    // Walks the screen pixels under the turned picture and looks up which source pixel each one shows
+    #[allow(clippy::too_many_arguments)]
+    fn blit_rotated(&mut self, src:&Texture, from: Rect, to: Vec2i, opts: BlitOptions, w: i32, h: i32, shade: impl Fn(&[u8]) -> Rgba) {
+        if w <= 0 || h <= 0 {
+            return;
+        }
+        let (sin, cos) = opts.rotation.to_radians().sin_cos();
+        let (ox, oy) = (opts.origin.0 as f32, opts.origin.1 as f32);
+        // The point everything turns around, on screen
+        let cx = (to.0 - self.position.0) as f32 + ox;
+        let cy = (to.1 - self.position.1) as f32 + oy;
+        // Bounding box of the turned corners
+        let corners = [(0.0, 0.0), (w as f32, 0.0), (0.0, h as f32), (w as f32, h as f32)];
+        let (mut min_x, mut min_y, mut max_x, mut max_y) = (f32::MAX, f32::MAX, f32::MIN, f32::MIN);
+        for (x, y) in corners.iter() {
+            let (dx, dy) = (x - ox, y - oy);
+            let (rx, ry) = (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos);
+            min_x = min_x.min(rx);
+            min_y = min_y.min(ry);
+            max_x = max_x.max(rx);
+            max_y = max_y.max(ry);
+        }
+        let x0 = (min_x.floor() as i32).max(0);
+        let x1 = (max_x.ceil() as i32).min(self.width as i32);
+        let y0 = (min_y.floor() as i32).max(0);
+        let y1 = (max_y.ceil() as i32).min(self.height as i32);
+        let depth = self.depth;
+        assert_eq!(depth, 4);
+        let src_depth = src.depth();
+        let src_pitch = src.pitch();
+        let dst_pitch = self.width * depth;
+        let src_buf = src.buffer();
+        for y in y0..y1 {
+            for x in x0..x1 {
+                // Turn the middle of this pixel back to find where it is on the unturned picture
+                let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+                let lx = dx * cos + dy * sin + ox;
+                let ly = -dx * sin + dy * cos + oy;
+                if lx < 0.0 || ly < 0.0 || lx >= w as f32 || ly >= h as f32 {
+                    continue;
+                }
+                let mut sx = ((lx * from.w as f32 / w as f32) as i32).min(from.w as i32 - 1);
+                let mut sy = ((ly * from.h as f32 / h as f32) as i32).min(from.h as i32 - 1);
+                if opts.flip_x {
+                    sx = from.w as i32 - 1 - sx;
+                }
+                if opts.flip_y {
+                    sy = from.h as i32 - 1 - sy;
+                }
+                let idx = (from.y + sy) as usize * src_pitch + (from.x + sx) as usize * src_depth;
+                let dst = y as usize * dst_pitch + x as usize * depth;
+                over(&mut self.framebuffer[dst..dst + depth], shade(&src_buf[idx..idx + src_depth]));
+            }
+        }
+    }
+    fn blit_with(&mut self, src:&Texture, from: Rect, Vec2i(to_x, to_y): Vec2i, shade: impl Fn(&[u8]) -> Rgba) {
         let (tw,th) = src.size();
         assert!(0 <= from.x);
         assert!(from.x < tw as i32);
@@ -132,7 +307,8 @@ impl<'fb> Screen<'fb> {
             return;
         }
         let depth = self.depth;
-        assert_eq!(depth, src.depth());
+        assert_eq!(depth, 4);
+        let src_depth = src.depth();
         let src_pitch = src.pitch();
         let dst_pitch = self.width * depth;
         // All this rigmarole is just to avoid bounds checks on each pixel of the blit.
@@ -154,17 +330,22 @@ impl<'fb> Screen<'fb> {
                 [(depth * (to_x + x_skip) as usize)..(depth * (to_x + x_count) as usize)]
                 .chunks_exact_mut(depth);
             let from_cols = row_a
-                [(depth * (from.x + x_skip) as usize)..(depth * (from.x + x_count) as usize)]
-                .chunks_exact(depth);
-            // Composite over, assume premultiplied rgba8888
+                [(src_depth * (from.x + x_skip) as usize)..(src_depth * (from.x + x_count) as usize)]
+                .chunks_exact(src_depth);
             for (to, from) in to_cols.zip(from_cols) {
-                let ta = to[3] as f32 / 255.0;
-                let fa = from[3] as f32 / 255.0;
-                for i in 0..3 {
-                    to[i] = from[i].saturating_add((to[i] as f32 * (1.0 - fa)).round() as u8);
-                }
-                to[3] = ((fa + ta * (1.0 - fa)) * 255.0).round() as u8;
+                over(to, shade(from));
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// Composite over, the framebuffer is premultiplied rgba8888
+fn over(to: &mut [u8], Rgba(r, g, b, a): Rgba) {
+    let from = [r, g, b, a];
+    let ta = to[3] as f32 / 255.0;
+    let fa = from[3] as f32 / 255.0;
+    for i in 0..3 {
+        to[i] = from[i].saturating_add((to[i] as f32 * (1.0 - fa)).round() as u8);
+    }
+    to[3] = ((fa + ta * (1.0 - fa)) * 255.0).round() as u8;
+}
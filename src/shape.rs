@@ -0,0 +1,339 @@
+use crate::types::Rect;
+
+/// The outline of a collider, in pixels from the top left of its rect.
+/// The rect should still cover the whole shape, since it's used to find what might be touching first.
+#[derive(PartialEq, Clone, Debug)]
+pub enum Shape {
+    /// The whole rect
+    Rect,
+    Circle { center: (f32, f32), radius: f32 },
+    /// A line with rounded ends, like a pill
+    Capsule { a: (f32, f32), b: (f32, f32), radius: f32 },
+    /// Convex, with the points going around it either way
+    Polygon(Vec<(f32, f32)>),
+}
+
+#[allow(dead_code)]
+impl Shape {
+    // Fills a rect 2*radius square
+    pub fn circle(radius: f32) -> Self {
+        Shape::Circle { center: (radius, radius), radius }
+    }
+    // A box that can be turned, unlike Shape::Rect
+    pub fn rect(w: f32, h: f32) -> Self {
+        Shape::Polygon(vec![(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)])
+    }
+    /// The same shape turned clockwise by `degrees` around `around`.
+    /// A `Shape::Rect` stays as it is, since it's always the collider's rect.
+    pub fn rotated(&self, degrees: f32, around: (f32, f32)) -> Shape {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let turn = |(x, y): (f32, f32)| {
+            let (dx, dy) = (x - around.0, y - around.1);
+            (around.0 + dx * cos - dy * sin, around.1 + dx * sin + dy * cos)
+        };
+        match self {
+            Shape::Rect => Shape::Rect,
+            Shape::Circle { center, radius } => Shape::Circle { center: turn(*center), radius: *radius },
+            Shape::Capsule { a, b, radius } => Shape::Capsule { a: turn(*a), b: turn(*b), radius: *radius },
+            Shape::Polygon(points) => Shape::Polygon(points.iter().map(|p| turn(*p)).collect()),
+        }
+    }
+    /// The smallest and largest x and y the shape reaches, or None for `Shape::Rect`.
+    pub fn bounds(&self) -> Option<((f32, f32), (f32, f32))> {
+        let hull = match self {
+            Shape::Rect => return None,
+            _ => hull(self, Rect { x: 0, y: 0, w: 0, h: 0 }),
+        };
+        let r = hull.radius;
+        let min = hull.points.iter().fold((f32::MAX, f32::MAX), |m, p| (m.0.min(p.0 - r), m.1.min(p.1 - r)));
+        let max = hull.points.iter().fold((f32::MIN, f32::MIN), |m, p| (m.0.max(p.0 + r), m.1.max(p.1 + r)));
+        Some((min, max))
+    }
+}
+
+// A shape where it is in the world: a convex core (a point, a line or a polygon) and how far around it counts
+struct Hull {
+    points: Vec<(f32, f32)>,
+    radius: f32,
+}
+
+fn hull(shape: &Shape, rect: Rect) -> Hull {
+    let (x, y) = (rect.x as f32, rect.y as f32);
+    let at = |(px, py): (f32, f32)| (x + px, y + py);
+    match shape {
+        Shape::Rect => {
+            let (w, h) = (rect.w as f32, rect.h as f32);
+            Hull { points: vec![(x, y), (x + w, y), (x + w, y + h), (x, y + h)], radius: 0.0 }
+        }
+        Shape::Circle { center, radius } => Hull { points: vec![at(*center)], radius: *radius },
+        Shape::Capsule { a, b, radius } => Hull { points: vec![at(*a), at(*b)], radius: *radius },
+        Shape::Polygon(points) => Hull { points: points.iter().map(|p| at(*p)).collect(), radius: 0.0 },
+    }
+}
+
+// Perpendiculars to every edge; a line has one edge, a point none
+fn edge_normals(points: &[(f32, f32)], into: &mut Vec<(f32, f32)>) {
+    let edges = match points.len() {
+        0 | 1 => 0,
+        2 => 1,
+        n => n,
+    };
+    for i in 0..edges {
+        let (p, q) = (points[i], points[(i + 1) % points.len()]);
+        into.push((p.1 - q.1, q.0 - p.0));
+    }
+}
+
+// Where the hull starts and stops along `axis`
+fn project(hull: &Hull, axis: (f32, f32)) -> (f32, f32) {
+    let (min, max) = hull.points.iter().map(|p| p.0 * axis.0 + p.1 * axis.1).fold((f32::MAX, f32::MIN), |(lo, hi), d| (lo.min(d), hi.max(d)));
+    (min - hull.radius, max + hull.radius)
+}
+
+/// Separating axis test between two shapes placed on their rects. If they overlap
+/// (touching counts), returns which way to push the first one out of the second, as a
+/// unit vector, and how far.
+pub fn overlap(a: &Shape, a_rect: Rect, b: &Shape, b_rect: Rect) -> Option<((f32, f32), f32)> {
+    let (a, b) = (hull(a, a_rect), hull(b, b_rect));
+    let mut axes = vec![];
+    edge_normals(&a.points, &mut axes);
+    edge_normals(&b.points, &mut axes);
+    // Round things touch corners or each other along the line between their closest points,
+    // which always runs between two of the core's points if it isn't across an edge
+    if a.radius > 0.0 || b.radius > 0.0 {
+        for p in a.points.iter() {
+            for q in b.points.iter() {
+                axes.push((p.0 - q.0, p.1 - q.1));
+            }
+        }
+    }
+    let mut best: Option<((f32, f32), f32)> = None;
+    for (x, y) in axes {
+        let length = (x * x + y * y).sqrt();
+        if length < 1e-6 {
+            continue;
+        }
+        let axis = (x / length, y / length);
+        let (a_min, a_max) = project(&a, axis);
+        let (b_min, b_max) = project(&b, axis);
+        // How far a would have to go each way along the axis to get clear
+        let forward = b_max - a_min;
+        let backward = a_max - b_min;
+        if forward < 0.0 || backward < 0.0 {
+            return None;
+        }
+        let push = if forward < backward { (axis, forward) } else { ((-axis.0, -axis.1), backward) };
+        if best.map_or(true, |(_, depth)| push.1 < depth) {
+            best = Some(push);
+        }
+    }
+    match best {
+        Some(push) => Some(push),
+        // Nothing to go by means both are points; they only touch in the same spot, and any way out will do
+        None if a.points.first() == b.points.first() => Some(((0.0, -1.0), a.radius + b.radius)),
+        None => None,
+    }
+}
+
+/// Where a ray from `from` along `dir` (a unit vector) first meets the shape placed on `rect`,
+/// if it does within `max` pixels: how far along, and the shape's outward normal there.
+/// Starting inside counts as a hit right away, facing back along the ray.
+pub fn raycast(shape: &Shape, rect: Rect, from: (f32, f32), dir: (f32, f32), max: f32) -> Option<(f32, (f32, f32))> {
+    ray_hull(&hull(shape, rect), from, dir, max)
+}
+
+/// Like `raycast`, but moving a whole shape (`cast` on `cast_rect`) along `dir` instead of a point.
+pub fn shapecast(cast: &Shape, cast_rect: Rect, dir: (f32, f32), max: f32, shape: &Shape, rect: Rect) -> Option<(f32, (f32, f32))> {
+    // Moving `cast` into `shape` is the same as moving a point into `shape` grown by `cast` turned inside out
+    let cast = hull(cast, cast_rect);
+    let flipped = Hull { points: cast.points.iter().map(|p| (-p.0, -p.1)).collect(), radius: cast.radius };
+    ray_hull(&minkowski(&hull(shape, rect), &flipped), (0.0, 0.0), dir, max)
+}
+
+/// Whether `p` is inside the shape placed on `rect`, edges included.
+pub fn contains(shape: &Shape, rect: Rect, p: (f32, f32)) -> bool {
+    hull_contains(&hull(shape, rect), p)
+}
+
+fn dot(a: (f32, f32), b: (f32, f32)) -> f32 {
+    a.0 * b.0 + a.1 * b.1
+}
+
+fn cross(a: (f32, f32), b: (f32, f32)) -> f32 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+// Two ends and a unit normal facing out
+type Edge = ((f32, f32), (f32, f32), (f32, f32));
+
+// A line faces out both ways, so it has each edge twice
+fn outward_edges(points: &[(f32, f32)]) -> Vec<Edge> {
+    let mut normals = vec![];
+    edge_normals(points, &mut normals);
+    let n = points.len() as f32;
+    let middle = points.iter().fold((0.0, 0.0), |m, p| (m.0 + p.0 / n, m.1 + p.1 / n));
+    let mut edges = vec![];
+    for (i, (x, y)) in normals.into_iter().enumerate() {
+        let length = (x * x + y * y).sqrt();
+        if length < 1e-6 {
+            continue;
+        }
+        let (p, q) = (points[i], points[(i + 1) % points.len()]);
+        let normal = (x / length, y / length);
+        if points.len() == 2 {
+            edges.push((p, q, normal));
+            edges.push((p, q, (-normal.0, -normal.1)));
+        } else if dot(normal, (p.0 - middle.0, p.1 - middle.1)) < 0.0 {
+            edges.push((p, q, (-normal.0, -normal.1)));
+        } else {
+            edges.push((p, q, normal));
+        }
+    }
+    edges
+}
+
+fn hull_contains(hull: &Hull, p: (f32, f32)) -> bool {
+    let edges = outward_edges(&hull.points);
+    if hull.points.len() > 2 && edges.iter().all(|(q, _, n)| dot(*n, (p.0 - q.0, p.1 - q.1)) <= 0.0) {
+        return true;
+    }
+    // Otherwise it has to be within the radius of the core's outline
+    let near = |q: (f32, f32)| dot((p.0 - q.0, p.1 - q.1), (p.0 - q.0, p.1 - q.1)) <= hull.radius * hull.radius;
+    hull.points.iter().any(|q| near(*q))
+        || edges.iter().any(|(a, b, _)| {
+            let e = (b.0 - a.0, b.1 - a.1);
+            let t = (dot((p.0 - a.0, p.1 - a.1), e) / dot(e, e)).clamp(0.0, 1.0);
+            near((a.0 + e.0 * t, a.1 + e.1 * t))
+        })
+}
+
+fn ray_hull(hull: &Hull, from: (f32, f32), dir: (f32, f32), max: f32) -> Option<(f32, (f32, f32))> {
+    if hull_contains(hull, from) {
+        return Some((0.0, (-dir.0, -dir.1)));
+    }
+    // The outline is the edges pushed out by the radius and circles around the corners; whichever
+    // of those the ray meets first is where it goes in, since they're all inside the hull
+    let mut best: Option<(f32, (f32, f32))> = None;
+    let mut consider = |t: f32, normal: (f32, f32)| {
+        if t <= max && best.map_or(true, |(first, _)| t < first) {
+            best = Some((t, normal));
+        }
+    };
+    if hull.radius > 0.0 {
+        for c in hull.points.iter() {
+            let m = (from.0 - c.0, from.1 - c.1);
+            let b = dot(m, dir);
+            let d = b * b - (dot(m, m) - hull.radius * hull.radius);
+            if d < 0.0 {
+                continue;
+            }
+            let t = -b - d.sqrt();
+            if t >= 0.0 {
+                let at = (from.0 + dir.0 * t, from.1 + dir.1 * t);
+                consider(t, ((at.0 - c.0) / hull.radius, (at.1 - c.1) / hull.radius));
+            }
+        }
+    }
+    for (p, q, n) in outward_edges(&hull.points) {
+        // Only coming at the edge from outside
+        if dot(dir, n) >= 0.0 {
+            continue;
+        }
+        let e = (q.0 - p.0, q.1 - p.1);
+        let p = (p.0 + n.0 * hull.radius, p.1 + n.1 * hull.radius);
+        let w = (p.0 - from.0, p.1 - from.1);
+        let denom = cross(dir, e);
+        if denom.abs() < 1e-6 {
+            continue;
+        }
+        let (t, s) = (cross(w, e) / denom, cross(w, dir) / denom);
+        if t >= 0.0 && (0.0..=1.0).contains(&s) {
+            consider(t, n);
+        }
+    }
+    best
+}
+
+// Every point of one added to every point of the other, wrapped up in a convex outline
+fn minkowski(a: &Hull, b: &Hull) -> Hull {
+    let sums = a.points.iter().flat_map(|p| b.points.iter().map(move |q| (p.0 + q.0, p.1 + q.1))).collect();
+    Hull { points: convex_hull(sums), radius: a.radius + b.radius }
+}
+
+// Monotone chain; a single point or a line if that's all there is
+fn convex_hull(mut points: Vec<(f32, f32)>) -> Vec<(f32, f32)> {
+    points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let turn = |o: (f32, f32), a: (f32, f32), b: (f32, f32)| cross((a.0 - o.0, a.1 - o.1), (b.0 - o.0, b.1 - o.1));
+    let mut out: Vec<(f32, f32)> = vec![];
+    // One way round the outline, then back along the other side
+    for p in points.iter() {
+        while out.len() >= 2 && turn(out[out.len() - 2], out[out.len() - 1], *p) <= 0.0 {
+            out.pop();
+        }
+        out.push(*p);
+    }
+    let bottom = out.len() + 1;
+    for p in points.iter().rev().skip(1) {
+        while out.len() >= bottom && turn(out[out.len() - 2], out[out.len() - 1], *p) <= 0.0 {
+            out.pop();
+        }
+        out.push(*p);
+    }
+    // Back where it started
+    out.pop();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4
+    }
+
+    #[test]
+    fn overlap_pushes_the_first_shape_out_the_short_way() {
+        let b = Rect { x: 8, y: 0, w: 10, h: 10 };
+        let (normal, depth) = overlap(&Shape::Rect, Rect { x: 0, y: 0, w: 10, h: 10 }, &Shape::Rect, b).unwrap();
+        assert!(close(normal, (-1.0, 0.0)));
+        assert!((depth - 2.0).abs() < 1e-4);
+        // Same overlap from the other side goes the other way
+        let (normal, depth) = overlap(&Shape::Rect, Rect { x: 16, y: 1, w: 10, h: 10 }, &Shape::Rect, b).unwrap();
+        assert!(close(normal, (1.0, 0.0)));
+        assert!((depth - 2.0).abs() < 1e-4);
+        assert!(overlap(&Shape::Rect, Rect { x: 0, y: 11, w: 10, h: 10 }, &Shape::Rect, b).is_none());
+    }
+
+    #[test]
+    fn capsule_end_pushes_along_the_line_between_centers() {
+        let capsule = Shape::Capsule { a: (0.0, 5.0), b: (20.0, 5.0), radius: 5.0 };
+        // A circle off the rounded end at (20, 5), 5 away from it diagonally
+        let (normal, depth) = overlap(&capsule, Rect { x: 0, y: 0, w: 25, h: 10 }, &Shape::circle(3.0), Rect { x: 20, y: 6, w: 6, h: 6 }).unwrap();
+        assert!(close(normal, (-0.6, -0.8)));
+        assert!((depth - 3.0).abs() < 1e-4);
+        // Off the flat side it's straight up
+        let (normal, depth) = overlap(&capsule, Rect { x: 0, y: 0, w: 25, h: 10 }, &Shape::circle(3.0), Rect { x: 7, y: 9, w: 6, h: 6 }).unwrap();
+        assert!(close(normal, (0.0, -1.0)));
+        assert!((depth - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn shapecast_stops_where_the_shapes_meet() {
+        let wall = Rect { x: 30, y: 0, w: 10, h: 10 };
+        let (t, normal) = shapecast(&Shape::Rect, Rect { x: 0, y: 0, w: 10, h: 10 }, (1.0, 0.0), 100.0, &Shape::Rect, wall).unwrap();
+        assert!((t - 20.0).abs() < 1e-4);
+        assert!(close(normal, (-1.0, 0.0)));
+        // A circle's front is its radius past the center
+        let (t, normal) = shapecast(&Shape::circle(5.0), Rect { x: 0, y: 2, w: 10, h: 10 }, (1.0, 0.0), 100.0, &Shape::Rect, wall).unwrap();
+        assert!((t - 20.0).abs() < 1e-4);
+        assert!(close(normal, (-1.0, 0.0)));
+        // Too short, or passing by underneath
+        assert!(shapecast(&Shape::Rect, Rect { x: 0, y: 0, w: 10, h: 10 }, (1.0, 0.0), 19.0, &Shape::Rect, wall).is_none());
+        assert!(shapecast(&Shape::Rect, Rect { x: 0, y: 11, w: 10, h: 10 }, (1.0, 0.0), 100.0, &Shape::Rect, wall).is_none());
+    }
+}
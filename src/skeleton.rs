@@ -0,0 +1,244 @@
+use crate::scene::Node;
+use crate::timeline::parse_ease;
+use crate::tween::{Ease, Lerp};
+use crate::types::Vec2i;
+use std::fs;
+use std::path::Path;
+
+/// Keyframes for one value of one bone. Between two keys the value follows the
+/// earlier key's easing; before the first and after the last it holds still.
+#[derive(Clone, Debug)]
+pub struct Track<T: Lerp> {
+    // time in seconds, value, easing towards the next key; sorted by time
+    keys: Vec<(f64, T, Ease)>,
+}
+
+#[allow(dead_code)]
+impl<T: Lerp> Track<T> {
+    pub fn new() -> Self {
+        Self { keys: vec![] }
+    }
+    pub fn key(&mut self, time: f64, value: T, ease: Ease) {
+        let index = self.keys.iter().position(|(t, _, _)| *t > time).unwrap_or(self.keys.len());
+        self.keys.insert(index, (time, value, ease));
+    }
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+    // None if there are no keys
+    pub fn sample(&self, time: f64) -> Option<T> {
+        let first = self.keys.first()?;
+        if time <= first.0 {
+            return Some(first.1);
+        }
+        for pair in self.keys.windows(2) {
+            let (t0, v0, ease) = pair[0];
+            let (t1, v1, _) = pair[1];
+            if time < t1 {
+                return Some(T::lerp(v0, v1, ease.apply((time - t0) / (t1 - t0))));
+            }
+        }
+        self.keys.last().map(|(_, v, _)| *v)
+    }
+}
+
+impl<T: Lerp> Default for Track<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a clip does to one bone. Channels without keys leave the node's own value alone.
+#[derive(Clone, Debug, Default)]
+pub struct BoneTracks {
+    pub position: Track<Vec2i>,
+    // degrees clockwise
+    pub rotation: Track<f32>,
+    pub scale: Track<f32>,
+}
+
+/// One motion for a whole skeleton, like a flap or an idle bob.
+#[derive(Clone, Debug)]
+pub struct Clip {
+    pub name: String,
+    // seconds
+    pub length: f64,
+    pub looping: bool,
+    // bone name, what happens to it
+    bones: Vec<(String, BoneTracks)>,
+}
+
+impl Clip {
+    pub fn new(name: &str, length: f64, looping: bool) -> Self {
+        Self { name: name.to_string(), length, looping, bones: vec![] }
+    }
+    /// The tracks for `bone`, added if it doesn't have any yet.
+    pub fn bone(&mut self, bone: &str) -> &mut BoneTracks {
+        let index = match self.bones.iter().position(|(name, _)| name == bone) {
+            Some(index) => index,
+            None => {
+                self.bones.push((bone.to_string(), BoneTracks::default()));
+                self.bones.len() - 1
+            }
+        };
+        &mut self.bones[index].1
+    }
+    /// Set the nodes named after this clip's bones to how they are `time` seconds in.
+    pub fn pose(&self, time: f64, nodes: &mut [Node]) {
+        for (bone, tracks) in self.bones.iter() {
+            let node = match nodes.iter_mut().find_map(|n| n.find_mut(bone)) {
+                Some(node) => node,
+                None => continue,
+            };
+            if let Some(offset) = tracks.position.sample(time) {
+                node.transform.offset = offset;
+            }
+            if let Some(rotation) = tracks.rotation.sample(time) {
+                node.transform.rotation = rotation;
+            }
+            if let Some(scale) = tracks.scale.sample(time) {
+                node.transform.scale = scale;
+            }
+        }
+    }
+}
+
+/// Cut-out animation: a set of clips that move, turn and scale the parts of a
+/// composite sprite. Every bone is a `Node`, found by its name.
+///
+/// In a file, a `clip` line starts a clip and the key lines after it belong to it;
+/// keys are a bone, a channel, a time and a value, with an optional easing
+/// (see `Timeline` for the names). `#` starts a comment:
+///
+/// ```text
+/// clip flap 0.3 once
+/// wing rotation 0.0 -40 quad_out
+/// wing rotation 0.3 0
+/// clip bob 1.0 loop
+/// wing position 0.0 0 0 quad_inout
+/// wing position 0.5 0 2 quad_inout
+/// wing position 1.0 0 0
+/// ```
+#[derive(Clone)]
+pub struct Skeleton {
+    clips: Vec<Clip>,
+    current: Option<usize>,
+    time: f64,
+    pub speed: f64,
+}
+
+#[allow(dead_code)]
+impl Skeleton {
+    pub fn new() -> Self {
+        Self { clips: vec![], current: None, time: 0.0, speed: 1.0 }
+    }
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut skeleton = Self::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            skeleton.parse_line(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        }
+        Ok(skeleton)
+    }
+    pub fn add_clip(&mut self, clip: Clip) {
+        self.clips.push(clip);
+    }
+    pub fn clip_mut(&mut self, name: &str) -> Option<&mut Clip> {
+        self.clips.iter_mut().find(|c| c.name == name)
+    }
+    /// Start the clip called `name` from the beginning. Unknown names stop the skeleton.
+    pub fn play(&mut self, name: &str) {
+        self.current = self.clips.iter().position(|c| c.name == name);
+        self.time = 0.0;
+    }
+    pub fn stop(&mut self) {
+        self.current = None;
+    }
+    pub fn playing(&self) -> Option<&str> {
+        self.current.map(|i| self.clips[i].name.as_str())
+    }
+    // Looping clips never finish
+    pub fn finished(&self) -> bool {
+        match self.current {
+            Some(i) => !self.clips[i].looping && self.time >= self.clips[i].length,
+            None => true,
+        }
+    }
+    pub fn advance(&mut self, dt: f64) {
+        let clip = match self.current {
+            Some(i) => &self.clips[i],
+            None => return,
+        };
+        self.time += dt * self.speed;
+        if clip.looping && clip.length > 0.0 {
+            self.time %= clip.length;
+        } else {
+            self.time = self.time.min(clip.length);
+        }
+    }
+    /// Move the bones in `nodes` (and their children) to where the current clip has them.
+    pub fn pose(&self, nodes: &mut [Node]) {
+        if let Some(i) = self.current {
+            self.clips[i].pose(self.time, nodes);
+        }
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words[0] == "clip" {
+            let (name, length, mode) = match words[1..] {
+                [name, length, mode] => (name, number(length)?, mode),
+                _ => return Err("clip needs a name, a length and loop or once".to_string()),
+            };
+            let looping = match mode {
+                "loop" => true,
+                "once" => false,
+                _ => return Err(format!("{} should be loop or once", mode)),
+            };
+            self.add_clip(Clip::new(name, length, looping));
+            return Ok(());
+        }
+        let clip = self.clips.last_mut().ok_or("key before any clip")?;
+        let (bone, channel) = match words[..] {
+            [bone, channel, _, _, ..] => (bone, channel),
+            _ => return Err("key needs a bone, a channel, a time and a value".to_string()),
+        };
+        let time = number(words[2])?;
+        // What's left after the value is the easing
+        let values = if channel == "position" { 2 } else { 1 };
+        let ease = match words.get(3 + values..) {
+            Some([]) => Ease::Linear,
+            Some([ease]) => parse_ease(ease)?,
+            Some(_) => return Err(format!("too many arguments for {}", channel)),
+            None => return Err(format!("missing value for {}", channel)),
+        };
+        let tracks = clip.bone(bone);
+        match channel {
+            "position" => {
+                let position = Vec2i(number(words[3])? as i32, number(words[4])? as i32);
+                tracks.position.key(time, position, ease);
+            }
+            "rotation" => tracks.rotation.key(time, number(words[3])? as f32, ease),
+            "scale" => tracks.scale.key(time, number(words[3])? as f32, ease),
+            _ => return Err(format!("unknown channel {}", channel)),
+        }
+        Ok(())
+    }
+}
+
+impl Default for Skeleton {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn number(word: &str) -> Result<f64, String> {
+    word.parse().map_err(|_| format!("{} isn't a number", word))
+}
@@ -1,4 +1,6 @@
 use std::collections::HashMap;
+use std::path::Path;
+use crate::hotreload::canonical;
 use kira::sound::handle::SoundHandle;
 use kira::instance::InstanceSettings;
 use kira::sound::SoundSettings;
@@ -9,6 +11,8 @@ use kira::manager::error::SetupError;
 
 pub struct Sound {
     sound_map: HashMap<String, SoundHandle>,
+    // where each sound was loaded from, for reloading
+    path_map: HashMap<String, String>,
     manager: Option<AudioManager>,
 }
 
@@ -17,8 +21,9 @@ impl Sound {
         let sound_map:HashMap<String, SoundHandle> = HashMap::new();
         let manager:Option<AudioManager> = None;
         Self{
-            sound_map: sound_map,
-            manager: manager,
+            sound_map,
+            path_map: HashMap::new(),
+            manager,
         }
     }
     pub fn init_manager(&mut self) -> Result<String, SetupError> {
@@ -30,9 +35,14 @@ impl Sound {
         let manager_o = &mut self.manager;
         match manager_o {
             Some(manager) => {
-                let handler_r = manager.load_sound(path, SoundSettings::default());
+                let handler_r = manager.load_sound(&path, SoundSettings::default());
                 match handler_r {
-                    Ok(handler) => {self.sound_map.insert(name, handler);},
+                    Ok(handler) => {
+                        if let Some(old) = self.sound_map.insert(name.clone(), handler) {
+                            let _ = manager.remove_sound(old.id());
+                        }
+                        self.path_map.insert(name, path);
+                    },
                     _ => println!("load sound error"),
                 }
             },
@@ -46,4 +56,22 @@ impl Sound {
             None => println!("missing sound"), 
         }
     }
-}
\ No newline at end of file
+    // Load any sounds that came from this file again; true if there were some
+    pub fn reload_path(&mut self, path: &Path) -> bool {
+        let path = canonical(path);
+        let names: Vec<(String, String)> = self.path_map.iter()
+            .filter(|(_, p)| canonical(Path::new(p)) == path)
+            .map(|(n, p)| (n.clone(), p.clone()))
+            .collect();
+        for (name, p) in names.iter() {
+            self.add_sound(name.clone(), p.clone());
+        }
+        !names.is_empty()
+    }
+}
+
+impl Default for Sound {
+    fn default() -> Self {
+        Self::new()
+    }
+}
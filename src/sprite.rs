@@ -1,48 +1,146 @@
-use crate::texture::Texture;
-use crate::types::{Rect, Vec2i};
+use crate::texture::{Palette, Texture};
+use crate::types::Vec2i;
 use std::rc::Rc;
 use crate::animation::{Animation};
+use crate::animstate::StateMachine;
 
 pub struct Sprite {
     image: Rc<Texture>,
-    pub frame: Rect, // Maybe better to use a type that can't have a negative origin
-    // Or use =animation:Animation= instead of a frame field
     pub position: Vec2i,
     //include imageheight/width?
     pub animations: Vec<Animation>,
     pub current_animation: usize,
+    // Picks current_animation by itself if it has any states
+    pub states: StateMachine,
+    // Draw an indexed image with this instead of its own palette
+    pub palette: Option<Palette>,
+    // frame events collected from the animations while advancing
+    events: Vec<String>,
+    // Which point of the frame goes at the position, as a fraction of its size:
+    // (0.0, 0.0) is the top left corner, (0.5, 1.0) the middle of the bottom edge.
+    // Frames with their own pivot use that instead.
+    pub anchor: (f32, f32),
+    pub flip_x: bool,
+    pub flip_y: bool,
+    // Degrees clockwise around the pivot
+    pub rotation: f32,
+    pub scale: f32,
+    pub opacity: f32,
+    pub visible: bool,
+    // Higher z is drawn on top by draw_sprites
+    pub z: i32,
 }
 
 #[allow(dead_code)]
 impl Sprite {
-    pub fn new(image: &Rc<Texture>, frame: Rect, position: Vec2i) -> Self {
+    pub fn new(image: &Rc<Texture>, position: Vec2i) -> Self {
         Self {
             image: Rc::clone(image),
-            frame,
             position,
             animations: Vec::new(),
             current_animation: 0,
+            states: StateMachine::new(),
+            palette: None,
+            events: vec![],
+            anchor: (0.0, 0.0),
+            flip_x: false,
+            flip_y: false,
+            rotation: 0.0,
+            scale: 1.0,
+            opacity: 1.0,
+            visible: true,
+            z: 0,
         }
     }
+    pub fn texture(&self) -> &Rc<Texture> {
+        &self.image
+    }
+    // Switching to another animation starts it from the beginning; picking the current one again does nothing
     pub fn set_animation(&mut self, index: usize) {
+        if index != self.current_animation {
+            self.animations[index].reset();
+        }
         self.current_animation = index;
     }
+    // Run the current animation forward by dt seconds; call from the game's update
+    pub fn advance(&mut self, dt: f64) {
+        let finished = self.animations.get(self.current_animation).is_some_and(|a| a.finished());
+        if let Some(index) = self.states.update(finished) {
+            self.set_animation(index);
+        }
+        if let Some(anim) = self.animations.get_mut(self.current_animation) {
+            anim.advance(dt);
+            self.events.extend(anim.take_events());
+        }
+    }
+    /// The point of the current frame (in unscaled, unflipped frame pixels) that goes at the sprite's position.
+    pub fn pivot(&self) -> Vec2i {
+        let frame = self.animations[self.current_animation].frame();
+        frame.pivot.unwrap_or_else(|| {
+            let (ax, ay) = self.anchor;
+            Vec2i((ax * frame.rect.w as f32).round() as i32, (ay * frame.rect.h as f32).round() as i32)
+        })
+    }
+    /// Frame events (see `Animation::add_event`) reached since the last call, oldest first.
+    pub fn take_events(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.events)
+    }
 }
 
+// Drawing only looks at the sprite; animations move on in Sprite::advance
 pub trait DrawSpriteExt {
-    fn draw_sprite(&mut self, s: &mut Sprite);
+    fn draw_sprite(&mut self, s: &Sprite);
+    fn draw_sprite_at(&mut self, s: &Sprite, position: Vec2i);
+    // Draw with a scene transform on top of the sprite's own flip, scale and so on;
+    // the pivot goes at the transform's offset
+    fn draw_sprite_transformed(&mut self, s: &Sprite, t: &Transform);
+    // Lowest z first; sprites with the same z keep their order
+    fn draw_sprites(&mut self, sprites: &[&Sprite]);
 }
 
-use crate::screen::Screen;
+use crate::scene::Transform;
+use crate::screen::{BlitOptions, Screen};
 impl<'fb> DrawSpriteExt for Screen<'fb> {
-    fn draw_sprite(&mut self, s: &mut Sprite) {
+    fn draw_sprite(&mut self, s: &Sprite) {
+        self.draw_sprite_at(s, s.position);
+    }
+    fn draw_sprite_at(&mut self, s: &Sprite, position: Vec2i) {
+        self.draw_sprite_transformed(s, &Transform::at(position));
+    }
+    fn draw_sprite_transformed(&mut self, s: &Sprite, t: &Transform) {
         // This works because we're only using a public method of Screen here,
         // and the private fields of sprite are visible inside this module
-        let ca = &mut s.animations[s.current_animation];
-        let x_pos = ca.current_frame * ca.frame_width;
-        let new_frame = Rect{x: x_pos as i32, y: ca.start_y, w: ca.frame_width, h: ca.frame_height};
-        ca.tick();
+        let ca = &s.animations[s.current_animation];
+        if !s.visible || !t.visible || !ca.visible() {
+            return;
+        }
+        let flip_x = s.flip_x != t.flip_x;
+        let flip_y = s.flip_y != t.flip_y;
+        let scale = s.scale * t.scale;
+        let rotation = t.rotation + if t.flip_x != t.flip_y { -s.rotation } else { s.rotation };
+        let rect = ca.frame().rect;
+        let Vec2i(mut px, mut py) = s.pivot();
+        // The pivot stays on the same bit of the picture when it's flipped
+        if flip_x {
+            px = rect.w as i32 - px;
+        }
+        if flip_y {
+            py = rect.h as i32 - py;
+        }
+        let origin = Vec2i((px as f32 * scale).round() as i32, (py as f32 * scale).round() as i32);
+        let position = Vec2i(t.offset.0 - origin.0, t.offset.1 - origin.1);
+        let opts = BlitOptions { flip_x, flip_y, scale, opacity: s.opacity * t.opacity, rotation, origin };
 
-        self.bitblt(&s.image, new_frame, s.position);
+        match &s.palette {
+            Some(palette) => self.bitblt_palette_with(&s.image, rect, position, palette, opts),
+            None => self.bitblt_with(&s.image, rect, position, opts),
+        }
+    }
+    fn draw_sprites(&mut self, sprites: &[&Sprite]) {
+        let mut sprites = sprites.to_vec();
+        sprites.sort_by_key(|s| s.z);
+        for s in sprites {
+            self.draw_sprite(s);
+        }
     }
 }
\ No newline at end of file
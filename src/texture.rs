@@ -1,97 +1,594 @@
-use crate::types::Rect;
-use image::{self, RgbaImage};
-use std::path::Path;
+// Savefile's derive puts its impls inside a const, which newer compilers warn about, and
+// writes its field version checks out as `from <= version && version <= to`
+#![allow(non_local_definitions, clippy::manual_range_contains, clippy::double_comparisons)]
+use crate::types::{Rect, Rgba};
+use image::codecs::png::PngEncoder;
+use image::{self, ColorType, ImageFormat, RgbaImage};
+use std::cell::{Ref, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
 extern crate savefile;
+use savefile::prelude::*;
 
-#[derive(Savefile)]
-pub struct Texture {
+/// How the bytes of a texture are laid out.
+/// Straight formats store colour and alpha independently; `Rgba8Premul` has colour
+/// already multiplied by alpha, which is what the screen composites with.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Savefile)]
+pub enum PixelFormat {
+    /// Luminance only (also used for font coverage masks)
+    L8,
+    /// Luminance + straight alpha
+    La8,
+    Rgb8,
+    /// Straight (not premultiplied) alpha
+    Rgba8,
+    /// Premultiplied alpha, the framebuffer's format
+    Rgba8Premul,
+    /// Straight alpha stored first, as some image sources deliver it
+    Argb8,
+    /// One byte per pixel indexing into the texture's `Palette`
+    Indexed8,
+}
+
+#[allow(dead_code)]
+impl PixelFormat {
+    pub fn depth(self) -> usize {
+        match self {
+            PixelFormat::L8 | PixelFormat::Indexed8 => 1,
+            PixelFormat::La8 => 2,
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Rgba8 | PixelFormat::Rgba8Premul | PixelFormat::Argb8 => 4,
+        }
+    }
+    // Old code passed around a bare depth; this is the format it always meant
+    pub fn from_depth(depth: usize) -> Result<Self, String> {
+        match depth {
+            1 => Ok(PixelFormat::L8),
+            2 => Ok(PixelFormat::La8),
+            3 => Ok(PixelFormat::Rgb8),
+            4 => Ok(PixelFormat::Rgba8Premul),
+            _ => Err(format!("No pixel format has depth {}", depth)),
+        }
+    }
+    pub fn has_alpha(self) -> bool {
+        !matches!(self, PixelFormat::L8 | PixelFormat::Rgb8)
+    }
+    /// Read one pixel as straight-alpha RGBA.
+    /// Indexed pixels need their palette, so without one they just read as a grey ramp.
+    pub fn read(self, px: &[u8]) -> Rgba {
+        match self {
+            PixelFormat::L8 | PixelFormat::Indexed8 => Rgba(px[0], px[0], px[0], 255),
+            PixelFormat::La8 => Rgba(px[0], px[0], px[0], px[1]),
+            PixelFormat::Rgb8 => Rgba(px[0], px[1], px[2], 255),
+            PixelFormat::Rgba8 => Rgba(px[0], px[1], px[2], px[3]),
+            PixelFormat::Rgba8Premul => unpremultiply(Rgba(px[0], px[1], px[2], px[3])),
+            PixelFormat::Argb8 => Rgba(px[1], px[2], px[3], px[0]),
+        }
+    }
+    /// Read one pixel as premultiplied RGBA, ready to composite.
+    pub fn read_premul(self, px: &[u8]) -> Rgba {
+        match self {
+            PixelFormat::L8 | PixelFormat::Rgb8 | PixelFormat::Indexed8 => self.read(px),
+            PixelFormat::Rgba8Premul => Rgba(px[0], px[1], px[2], px[3]),
+            _ => premultiply(self.read(px)),
+        }
+    }
+    /// How much of a pixel should be drawn when it is used as a mask:
+    /// alpha if the format has one, luminance otherwise.
+    pub fn coverage(self, px: &[u8]) -> u8 {
+        match self {
+            PixelFormat::L8 | PixelFormat::Rgb8 | PixelFormat::Indexed8 => luminance(self.read(px)),
+            PixelFormat::La8 => px[1],
+            PixelFormat::Rgba8 | PixelFormat::Rgba8Premul => px[3],
+            PixelFormat::Argb8 => px[0],
+        }
+    }
+    /// Write a straight-alpha colour into one pixel of this format.
+    pub fn write(self, col: Rgba, px: &mut [u8]) {
+        match self {
+            PixelFormat::L8 | PixelFormat::Indexed8 => px[0] = luminance(col),
+            PixelFormat::La8 => {
+                px[0] = luminance(col);
+                px[1] = col.3;
+            }
+            PixelFormat::Rgb8 => px.copy_from_slice(&[col.0, col.1, col.2]),
+            PixelFormat::Rgba8 => px.copy_from_slice(&[col.0, col.1, col.2, col.3]),
+            PixelFormat::Rgba8Premul => {
+                let Rgba(r, g, b, a) = premultiply(col);
+                px.copy_from_slice(&[r, g, b, a]);
+            }
+            PixelFormat::Argb8 => px.copy_from_slice(&[col.3, col.0, col.1, col.2]),
+        }
+    }
+}
+
+/// Up to 256 straight-alpha colours for an `Indexed8` texture.
+/// Swapping or cycling entries recolours everything drawn with the palette without touching the pixels.
+#[derive(Clone, Debug, Savefile)]
+pub struct Palette {
+    colors: Vec<Rgba>,
+    // The blitter's lookup table, made when it's first needed and thrown away when a colour changes
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    lut: RefCell<Option<Vec<Rgba>>>,
+}
+
+impl PartialEq for Palette {
+    fn eq(&self, other: &Self) -> bool {
+        self.colors == other.colors
+    }
+}
+
+#[allow(dead_code)]
+impl Palette {
+    pub fn new(colors: Vec<Rgba>) -> Self {
+        assert!(colors.len() <= 256, "A palette can only hold 256 colors");
+        Self { colors, lut: RefCell::new(None) }
+    }
+    pub fn colors(&self) -> &[Rgba] {
+        &self.colors
+    }
+    // Indices past the end of the palette are transparent
+    pub fn get(&self, index: u8) -> Rgba {
+        self.colors.get(index as usize).copied().unwrap_or(Rgba(0, 0, 0, 0))
+    }
+    // Setting an index past the end does nothing
+    pub fn set(&mut self, index: u8, col: Rgba) {
+        if let Some(c) = self.colors.get_mut(index as usize) {
+            *c = col;
+            self.lut.replace(None);
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+    // Index of an exact colour match, if any
+    pub fn find(&self, col: Rgba) -> Option<u8> {
+        self.colors.iter().position(|c| *c == col).map(|i| i as u8)
+    }
+    /// A copy of this palette with every occurrence of `from` replaced by `to`,
+    /// e.g. to turn a red bird blue.
+    pub fn recolor(&self, from: Rgba, to: Rgba) -> Palette {
+        Palette::new(self.colors.iter().map(|c| if *c == from { to } else { *c }).collect())
+    }
+    /// Rotate the entries in `first..=last` by `steps`, for water shimmer and flashing effects.
+    /// `last` stops at the end of the palette, and an empty range does nothing.
+    pub fn cycle(&mut self, first: u8, last: u8, steps: usize) {
+        let last = (last as usize).min(self.colors.len().saturating_sub(1));
+        if self.colors.is_empty() || first as usize >= last {
+            return;
+        }
+        let slice = &mut self.colors[first as usize..=last];
+        let steps = steps % slice.len();
+        slice.rotate_right(steps);
+        self.lut.replace(None);
+    }
+    /// Premultiplied lookup table for the blitter, always 256 entries long.
+    pub fn premultiplied(&self) -> Ref<'_, [Rgba]> {
+        if self.lut.borrow().is_none() {
+            self.lut.replace(Some((0..=255).map(|i| premultiply(self.get(i))).collect()));
+        }
+        Ref::map(self.lut.borrow(), |lut| lut.as_deref().unwrap())
+    }
+}
+
+/// The pixels of a texture.  Kept apart from `Texture` so a texture can be
+/// swapped out in place (see `Texture::replace`) while sprites and tilemaps hold on to it.
+#[derive(Clone, Savefile)]
+pub struct TextureData {
     pub image: Vec<u8>,
     pub width: usize,
     pub height: usize,
-    pub depth: usize,
+    // Version 0 saves only stored the depth, and every saved texture was premultiplied rgba
+    #[savefile_versions_as = "0..0:format_of_depth:usize"]
+    #[savefile_versions = "1.."]
+    pub format: PixelFormat,
+    // Only Indexed8 textures have one
+    #[savefile_versions = "2.."]
+    pub palette: Option<Palette>,
 }
-#[allow(dead_code)]
-enum AlphaChannel {
-    First,
-    Last,
+
+fn format_of_depth(depth: usize) -> PixelFormat {
+    // Those saves only ever had depth 4, so anything else is junk; go with what they all were
+    PixelFormat::from_depth(depth).unwrap_or(PixelFormat::Rgba8Premul)
+}
+
+pub struct Texture {
+    // Shared with any texture loaded from a save that points at the same file
+    data: Rc<RefCell<TextureData>>,
+    // Where the texture was loaded from, so it can be reloaded
+    source: Option<PathBuf>,
+    // Save the pixels themselves even if there's a file to point at
+    embed: bool,
+}
+
+thread_local! {
+    // Pixels of every texture loaded from a file that's still around, so loading a save
+    // can share them instead of reading and decoding the file all over again
+    static LOADED: RefCell<Vec<(PathBuf, Weak<RefCell<TextureData>>)>> = const { RefCell::new(Vec::new()) };
+}
+
+// The first version of the save format where textures refer to their files
+const ASSET_SAVE_VERSION: u32 = 3;
+
+/// What actually goes in a savefile for a texture.
+/// A texture loaded from a file is saved as the path (plus a hash of the pixels to notice when
+/// the file changed); anything else is saved as PNG-compressed pixel data.
+#[derive(Savefile)]
+enum SavedTexture {
+    File {
+        path: String,
+        format: PixelFormat,
+        hash: u64,
+    },
+    Embedded {
+        width: usize,
+        height: usize,
+        format: PixelFormat,
+        palette: Option<Palette>,
+        // The raw bytes of the texture, whatever the format, squeezed with PNG's compression
+        png: Vec<u8>,
+    },
+}
+
+// Before version 3 a texture was saved as its whole TextureData
+impl WithSchema for Texture {
+    fn schema(version: u32) -> Schema {
+        if version < ASSET_SAVE_VERSION {
+            TextureData::schema(version)
+        } else {
+            SavedTexture::schema(version)
+        }
+    }
+}
+impl Serialize for Texture {
+    fn serialize(&self, serializer: &mut Serializer) -> Result<(), SavefileError> {
+        if serializer.version < ASSET_SAVE_VERSION {
+            return self.data.borrow().serialize(serializer);
+        }
+        self.to_saved()?.serialize(serializer)
+    }
+}
+impl Deserialize for Texture {
+    fn deserialize(deserializer: &mut Deserializer) -> Result<Self, SavefileError> {
+        if deserializer.file_version < ASSET_SAVE_VERSION {
+            return Ok(Texture::from_data(TextureData::deserialize(deserializer)?));
+        }
+        Texture::from_saved(SavedTexture::deserialize(deserializer)?)
+            .map_err(|msg| SavefileError::GeneralError { msg })
+    }
+}
+impl Introspect for Texture {
+    fn introspect_value(&self) -> String {
+        (*self.data).introspect_value()
+    }
+    fn introspect_child(&self, index: usize) -> Option<Box<dyn IntrospectItem<'_> + '_>> {
+        (*self.data).introspect_child(index)
+    }
 }
+
+impl Texture {
+    fn to_saved(&self) -> Result<SavedTexture, SavefileError> {
+        let data = self.data();
+        match &self.source {
+            Some(path) if !self.embed => Ok(SavedTexture::File {
+                path: path.to_string_lossy().into_owned(),
+                format: data.format,
+                hash: self.content_hash(),
+            }),
+            _ => {
+                // PNG has a color type for each depth, so the bytes go through untouched
+                let color = match data.format.depth() {
+                    1 => ColorType::L8,
+                    2 => ColorType::La8,
+                    3 => ColorType::Rgb8,
+                    _ => ColorType::Rgba8,
+                };
+                let mut png = vec![];
+                if data.width * data.height > 0 {
+                    PngEncoder::new(&mut png)
+                        .encode(&data.image, data.width as u32, data.height as u32, color)
+                        .map_err(|e| SavefileError::GeneralError { msg: e.to_string() })?;
+                }
+                Ok(SavedTexture::Embedded {
+                    width: data.width,
+                    height: data.height,
+                    format: data.format,
+                    palette: data.palette.clone(),
+                    png,
+                })
+            }
+        }
+    }
+    fn from_saved(saved: SavedTexture) -> Result<Texture, String> {
+        match saved {
+            SavedTexture::File { path, format, hash } => {
+                let path = PathBuf::from(path);
+                // The game has it loaded already (and hot reload keeps it up to date), so share that
+                if let Some(tex) = Texture::already_loaded(&path, format) {
+                    return Ok(tex);
+                }
+                let tex = Texture::load_as(&path, format)?;
+                if tex.content_hash() != hash {
+                    eprintln!("{} has changed since it was saved", path.display());
+                }
+                Ok(tex)
+            }
+            SavedTexture::Embedded { width, height, format, palette, png } => {
+                let image = if png.is_empty() {
+                    vec![]
+                } else {
+                    image::load_from_memory_with_format(&png, ImageFormat::Png)
+                        .map_err(|e| e.to_string())?
+                        .as_bytes()
+                        .to_vec()
+                };
+                if image.len() != width * height * format.depth() {
+                    return Err("Embedded texture is the wrong size".to_string());
+                }
+                Ok(Texture::from_data(TextureData { image, width, height, format, palette }))
+            }
+        }
+    }
+}
+
 #[allow(dead_code)]
 impl Texture {
     pub fn with_file(path: &Path) -> Self {
-        Self::new(image::open(path).expect("Couldn't load image").into_rgba8())
+        Self::new(image::open(path).expect("Couldn't load image").into_rgba8()).loaded_from(path)
+    }
+    // Load a file and bring it into the given format, remembering where it came from
+    fn load_as(path: &Path, format: PixelFormat) -> Result<Self, String> {
+        let image = image::open(path).map_err(|e| e.to_string())?;
+        let fresh = Texture::new(image.into_rgba8());
+        let tex = match format {
+            PixelFormat::Indexed8 => fresh.to_indexed().ok_or("too many colors to index")?,
+            _ => fresh.convert(format),
+        };
+        Ok(tex.loaded_from(path))
+    }
+    // Remember where the pixels came from, for reloading and for saves to share
+    fn loaded_from(mut self, path: &Path) -> Self {
+        self.source = Some(path.to_path_buf());
+        LOADED.with(|loaded| {
+            let mut loaded = loaded.borrow_mut();
+            loaded.retain(|(_, data)| data.strong_count() > 0);
+            loaded.push((path.to_path_buf(), Rc::downgrade(&self.data)));
+        });
+        self
+    }
+    // Another handle on the pixels of a texture loaded from `path` in `format`, if one's still around
+    fn already_loaded(path: &Path, format: PixelFormat) -> Option<Self> {
+        let data = LOADED.with(|loaded| {
+            loaded
+                .borrow()
+                .iter()
+                .filter(|(p, _)| p == path)
+                .filter_map(|(_, data)| data.upgrade())
+                .find(|data| data.borrow().format == format)
+        })?;
+        Some(Self { data, source: Some(path.to_path_buf()), embed: false })
     }
     pub fn new(image: RgbaImage) -> Self {
         let (width, height) = image.dimensions();
-        let mut image = image.into_vec();
-        premultiply(&mut image, 4, AlphaChannel::Last);
+        Self::with_format(image.into_vec(), width as usize, height as usize, PixelFormat::Rgba8)
+            .convert(PixelFormat::Rgba8Premul)
+    }
+    // Old-style bare depth; use with_format to say what the bytes are
+    pub fn from_vec(vec: Vec<u8>, width: usize, height: usize, depth: usize) -> Result<Self, String> {
+        Ok(Self::with_format(vec, width, height, PixelFormat::from_depth(depth)?))
+    }
+    pub fn with_format(vec: Vec<u8>, width: usize, height: usize, format: PixelFormat) -> Self {
+        assert_eq!(vec.len(), width * height * format.depth(), "Texture data is the wrong size!");
+        assert!(format != PixelFormat::Indexed8, "Indexed textures need a palette, use with_palette");
+        Self::from_data(TextureData {
+            width,
+            height,
+            format,
+            image: vec,
+            palette: None,
+        })
+    }
+    pub fn with_palette(vec: Vec<u8>, width: usize, height: usize, palette: Palette) -> Self {
+        assert_eq!(vec.len(), width * height, "Texture data is the wrong size!");
+        Self::from_data(TextureData {
+            width,
+            height,
+            format: PixelFormat::Indexed8,
+            image: vec,
+            palette: Some(palette),
+        })
+    }
+    pub fn from_data(data: TextureData) -> Self {
         Self {
-            width: width as usize,
-            height: height as usize,
-            depth: 4,
-            image: image.to_vec(),
+            data: Rc::new(RefCell::new(data)),
+            source: None,
+            embed: false,
         }
     }
-    // for fonts
-    pub fn from_vec(vec: Vec<u8>, width: usize, height: usize, depth: usize) -> Self {
-        Self {
-            width: width,
-            height: height,
-            depth: depth,
-            image: vec,
+    // Load an image and index it straight away; see `to_indexed`
+    pub fn with_file_indexed(path: &Path) -> Self {
+        Self::load_as(path, PixelFormat::Indexed8).expect("Couldn't load image as indexed")
+    }
+    /// Borrow the pixels.  Don't hold on to this across a `replace`.
+    pub fn data(&self) -> Ref<'_, TextureData> {
+        self.data.borrow()
+    }
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+    /// Swap in new pixels; everyone holding this texture (or sharing its pixels, see `from_saved`)
+    /// sees them from now on.
+    pub fn replace(&self, other: Texture) {
+        *self.data.borrow_mut() = other.into_data();
+    }
+    // The pixels, copied if they're shared
+    fn into_data(self) -> TextureData {
+        Rc::try_unwrap(self.data).map(RefCell::into_inner).unwrap_or_else(|data| data.borrow().clone())
+    }
+    /// Load the texture again from `path`, keeping its current pixel format.
+    /// Anything done to it after loading (resizing, say) has to be redone by the caller.
+    pub fn reload_from(&self, path: &Path) -> Result<(), String> {
+        let fresh = Texture::load_as(path, self.format())?;
+        self.replace(fresh);
+        Ok(())
+    }
+    /// Save the pixels into savefiles instead of just the path of the file they came from.
+    /// Worth it for files that might not be around when the save is loaded.
+    pub fn embedded(mut self) -> Self {
+        self.embed = true;
+        self
+    }
+    /// A hash of the size, format, pixels and palette (FNV-1a), to tell textures apart cheaply.
+    pub fn content_hash(&self) -> u64 {
+        let data = self.data();
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let header = [data.width as u64, data.height as u64, data.format.depth() as u64];
+        let palette = data.palette.iter().flat_map(|p| p.colors.iter().flat_map(|c| vec![c.0, c.1, c.2, c.3]));
+        let bytes = header
+            .iter()
+            .flat_map(|n| n.to_le_bytes().to_vec())
+            .chain(data.image.iter().copied())
+            .chain(palette);
+        for byte in bytes {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
         }
+        hash
     }
     pub fn depth(&self) -> usize {
-        self.depth
+        self.format().depth()
+    }
+    pub fn format(&self) -> PixelFormat {
+        self.data.borrow().format
     }
     pub fn size(&self) -> (usize, usize) {
-        (self.width, self.height)
+        let data = self.data.borrow();
+        (data.width, data.height)
+    }
+    pub fn width(&self) -> usize {
+        self.data.borrow().width
+    }
+    pub fn height(&self) -> usize {
+        self.data.borrow().height
     }
     pub fn pitch(&self) -> usize {
-        self.width * self.depth
+        self.width() * self.depth()
     }
-    pub fn buffer(&self) -> &[u8] {
-        &self.image
+    pub fn buffer(&self) -> Ref<'_, [u8]> {
+        Ref::map(self.data.borrow(), |d| d.image.as_slice())
     }
     pub fn valid_frame(&self, frame: Rect) -> bool {
+        let (width, height) = self.size();
         0 <= frame.x
-            && (frame.x + frame.w as i32) <= (self.width as i32)
+            && (frame.x + frame.w as i32) <= (width as i32)
             && 0 <= frame.y
-            && (frame.y + frame.h as i32) <= (self.height as i32)
+            && (frame.y + frame.h as i32) <= (height as i32)
     }
-    pub fn convert_to_rgba(&mut self) {
-        if self.depth != 1 {
-            return;
+    // Borrowed, so the palette's lookup table sticks around between blits
+    pub fn palette(&self) -> Option<Ref<'_, Palette>> {
+        Ref::filter_map(self.data.borrow(), |d| d.palette.as_ref()).ok()
+    }
+    /// Straight-alpha colour of the pixel at (x, y).
+    pub fn pixel(&self, x: usize, y: usize) -> Rgba {
+        let data = self.data();
+        let depth = data.format.depth();
+        let idx = (y * data.width + x) * depth;
+        data.read(&data.image[idx..(idx + depth)])
+    }
+    /// Build an indexed copy of this texture, with one palette entry per distinct colour.
+    /// Returns None if there are more than 256 colours.
+    pub fn to_indexed(&self) -> Option<Texture> {
+        let data = self.data();
+        let mut colors: Vec<Rgba> = vec![];
+        let mut indices = Vec::with_capacity(data.width * data.height);
+        for px in data.image.chunks_exact(data.format.depth()) {
+            let col = data.read(px);
+            let idx = match colors.iter().position(|c| *c == col) {
+                Some(idx) => idx,
+                None => {
+                    if colors.len() == 256 {
+                        return None;
+                    }
+                    colors.push(col);
+                    colors.len() - 1
+                }
+            };
+            indices.push(idx as u8);
         }
-        let mut i = 0;
-        let mut new_image:Vec<u8> = vec![];
-        while i < self.width * self.height {
-            new_image.push(self.image[i]);
-            new_image.push(self.image[i]);
-            new_image.push(self.image[i]);
-            new_image.push(255);
-            i+=1;
+        Some(Texture::with_palette(indices, data.width, data.height, Palette::new(colors)))
+    }
+    /// Make a copy of this texture in another format.
+    /// Going from premultiplied to straight and back to premultiplied gives the same pixels;
+    /// the other way round loses colour in translucent pixels, since premultiplying rounds it off.
+    /// Dropping colour or alpha of course can't be undone either.
+    pub fn convert(&self, format: PixelFormat) -> Texture {
+        let data = self.data();
+        if format == data.format {
+            return Texture::from_data(TextureData {
+                image: data.image.clone(),
+                palette: data.palette.clone(),
+                ..*data
+            });
+        }
+        if format == PixelFormat::Indexed8 {
+            return self.to_indexed().expect("Texture has too many colors to index");
+        }
+        let mut new_image = vec![0; data.width * data.height * format.depth()];
+        for (from, to) in data
+            .image
+            .chunks_exact(data.format.depth())
+            .zip(new_image.chunks_exact_mut(format.depth()))
+        {
+            format.write(data.read(from), to);
+        }
+        Texture::with_format(new_image, data.width, data.height, format)
+    }
+    pub fn convert_to(&mut self, format: PixelFormat) {
+        if format != self.format() {
+            let converted = self.convert(format);
+            self.replace(converted);
+        }
+    }
+    pub fn convert_to_rgba(&mut self) {
+        self.convert_to(PixelFormat::Rgba8Premul);
+    }
+}
+
+impl TextureData {
+    // Read a pixel as straight rgba, going through the palette if there is one
+    fn read(&self, px: &[u8]) -> Rgba {
+        match &self.palette {
+            Some(palette) => palette.get(px[0]),
+            None => self.format.read(px),
         }
-        self.image = new_image;
-        self.depth = 4;
     }
 }
 
 pub fn stack_horizontal(textures: Vec<Texture>) -> Texture {
     let mut new_image: Vec<u8> = vec![];
-    if textures.len() == 0 {
-        return Texture{image: new_image, width: 0, height: 0, depth: 0};
+    if textures.is_empty() {
+        return Texture::with_format(new_image, 0, 0, PixelFormat::Rgba8Premul);
     }
-    
+
     let mut row = 0;
     let mut texture = 0;
     let mut column;
     let mut channel;
     let mut total_width = 0;
-    let mut max_height = textures[0].height;
     let sample = &textures[0];
     let texture_count = textures.len();
-    let depth = sample.depth;
-    
+    let format = sample.format();
+    // Everything gets stacked in the first texture's format.
+    // Indexed textures might not share a palette, so those get expanded to rgba.
+    let format = if format == PixelFormat::Indexed8 { PixelFormat::Rgba8Premul } else { format };
+    let depth = format.depth();
+    let textures: Vec<TextureData> = textures.iter().map(|t| t.convert(format).into_data()).collect();
+    let mut max_height = textures[0].height;
+
     while texture < texture_count {
         total_width += textures[texture].width;
         if max_height < textures[texture].height {
@@ -130,33 +627,26 @@ pub fn stack_horizontal(textures: Vec<Texture>) -> Texture {
         }
         row += 1;
     }
-    Texture{image: new_image, width: total_width, height: height, depth: depth}
+    Texture::with_format(new_image, total_width, height, format)
 
 }
 
-fn premultiply(img: &mut [u8], depth: usize, alpha: AlphaChannel) {
-    match alpha {
-        AlphaChannel::First => {
-            for px in img.chunks_exact_mut(depth) {
-                let a = px[0] as f32 / 255.0;
-                for component in px[1..].iter_mut() {
-                    *component = (*component as f32 * a).round() as u8;
-                }
-                // swap around to rgba8888
-                let a = px[0];
-                px[0] = px[1];
-                px[1] = px[2];
-                px[2] = px[3];
-                px[3] = a;
-            }
-        }
-        AlphaChannel::Last => {
-            for px in img.chunks_exact_mut(depth) {
-                let a = *px.last().unwrap() as f32 / 255.0;
-                for component in px[0..(depth - 1)].iter_mut() {
-                    *component = (*component as f32 * a) as u8;
-                }
-            }
-        }
+// Rec. 601 weights in fixed point; grey stays exactly grey
+fn luminance(col: Rgba) -> u8 {
+    ((col.0 as u32 * 77 + col.1 as u32 * 150 + col.2 as u32 * 29) >> 8) as u8
+}
+
+pub fn premultiply(col: Rgba) -> Rgba {
+    let a = col.3 as u32;
+    let mul = |c: u8| ((c as u32 * a + 127) / 255) as u8;
+    Rgba(mul(col.0), mul(col.1), mul(col.2), col.3)
+}
+
+pub fn unpremultiply(col: Rgba) -> Rgba {
+    let a = col.3 as u32;
+    if a == 0 {
+        return Rgba(0, 0, 0, 0);
     }
-}
\ No newline at end of file
+    let div = |c: u8| ((c as u32 * 255 + a / 2) / a).min(255) as u8;
+    Rgba(div(col.0), div(col.1), div(col.2), col.3)
+}
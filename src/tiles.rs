@@ -1,4 +1,7 @@
-use crate::texture::Texture;
+// Savefile's derive puts its impls inside a const, which newer compilers warn about, and
+// writes its field version checks out as `from <= version && version <= to`
+#![allow(non_local_definitions, clippy::manual_range_contains, clippy::double_comparisons)]
+use crate::texture::{Palette, Texture};
 use crate::types::{Rect, Vec2i};
 use crate::screen::{Screen};
 use std::rc::Rc;
@@ -6,7 +9,7 @@ extern crate savefile;
 
 pub const TILE_SZ: usize = 16;
 
-Windows & Linux:
+//Windows & Linux:
 pub const SZ: usize = 16;
 //Mac:
 //pub const SZ: usize = 32;
@@ -17,6 +20,41 @@ pub struct Tile {
     pub oppgrid: bool, //whether tile is part of opponent's grid or player's grid
     pub opphit: bool,  //whether opponent has ship in that tile, switch if it is hit
     pub myship: bool,  // whether player has a ship in that tile, switch if it is hit
+    // How mobiles moving through a Tilemap treat this tile
+    #[savefile_versions = "4.."]
+    pub collision: TileCollision,
+}
+
+/// What a tile is like to bump into, for `Mobile::move_in_tilemap`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Savefile)]
+pub enum TileCollision {
+    #[default]
+    Empty,
+    Solid,
+    /// Only stops things landing on it from above; they can jump up through it
+    OneWay,
+    /// A floor going from `left` to `right` pixels above the tile's bottom, e.g. 0 and 16 for a
+    /// 45° slope up to the right, or 0 and 8 then 8 and 16 for a 22.5° one over two tiles
+    Slope { left: u8, right: u8 },
+    /// Doesn't stop anything, just gets reported
+    Ladder,
+    Hazard,
+}
+
+impl TileCollision {
+    /// How high the floor of a slope is `x` pixels into the tile; 0 for anything else.
+    pub fn height_at(self, x: i32) -> i32 {
+        match self {
+            TileCollision::Slope { left, right } => {
+                let (left, right) = (left as f32, right as f32);
+                // The first and last pixels are right on left and right, so slopes meet flat
+                // floors and each other without a step
+                let t = x.max(0).min(TILE_SZ as i32 - 1) as f32 / (TILE_SZ - 1) as f32;
+                (left + (right - left) * t).round() as i32
+            }
+            _ => 0,
+        }
+    }
 }
 /// A set of tiles used in multiple Tilemaps
 #[derive(Clone, Savefile)]
@@ -30,8 +68,6 @@ pub struct Tileset {
  /// Indices into a Tileset
 #[derive(Clone, Copy, PartialEq, Eq, Savefile)]
 pub struct TileID(usize); 
-/// Indices into a Tileset
-
 
 /// Grab a tile with a given ID
 impl std::ops::Index<TileID> for Tileset {
@@ -79,6 +115,9 @@ pub struct Tilemap {
     pub tileset: Rc<Tileset>,
     /// A row-major grid of tile IDs in tileset
     map: Vec<TileID>,
+    /// Draw an indexed tileset with this palette instead of its own (e.g. team colors)
+    #[savefile_versions = "2.."]
+    pub palette: Option<Palette>,
 }
 
 impl Tilemap {
@@ -98,6 +137,7 @@ impl Tilemap {
             dims,
             tileset: Rc::clone(tileset),
             map: map.into_iter().map(TileID).collect(),
+            palette: None,
         }
     }
 
@@ -152,6 +192,33 @@ impl Tilemap {
         self.dims
     }
 
+    /// How the tile in column `col` and row `row` collides; outside the map is empty.
+    pub fn collision_at(&self, col: i32, row: i32) -> TileCollision {
+        if col < 0 || row < 0 || col >= self.dims.0 as i32 || row >= self.dims.1 as i32 {
+            return TileCollision::Empty;
+        }
+        self.tileset[self.map[row as usize * self.dims.0 + col as usize]].collision
+    }
+
+    /// The tile in column `col` and row `row`, in world coordinates.
+    pub fn cell_rect(&self, col: i32, row: i32) -> Rect {
+        Rect {
+            x: self.position.0 + col * TILE_SZ as i32,
+            y: self.position.1 + row * TILE_SZ as i32,
+            w: TILE_SZ as u16,
+            h: TILE_SZ as u16,
+        }
+    }
+
+    /// The columns and rows of the tiles `r` covers, maybe partly outside the map.
+    pub fn cells_under(&self, r: Rect) -> (std::ops::RangeInclusive<i32>, std::ops::RangeInclusive<i32>) {
+        let t = TILE_SZ as i32;
+        let (x, y) = (r.x - self.position.0, r.y - self.position.1);
+        let cols = x.div_euclid(t)..=(x + r.w as i32 - 1).div_euclid(t);
+        let rows = y.div_euclid(t)..=(y + r.h as i32 - 1).div_euclid(t);
+        (cols, rows)
+    }
+
     //input: window coordinates
     //output: Tile
     pub fn tile_at(&self, posn: Vec2i) -> Tile {
@@ -223,7 +290,10 @@ impl Tilemap {
                 let xpx = (x * TILE_SZ) as i32 + self.position.0;
                 let frame = self.tileset.get_rect(*id);
 
-                screen.bitblt(&self.tileset.texture, frame, Vec2i(xpx, ypx));
+                match &self.palette {
+                    Some(palette) => screen.bitblt_palette(&self.tileset.texture, frame, Vec2i(xpx, ypx), palette),
+                    None => screen.bitblt(&self.tileset.texture, frame, Vec2i(xpx, ypx)),
+                }
             }
         }
     }
@@ -0,0 +1,241 @@
+use crate::tween::{Curve, Ease, Tween, Tweening};
+use crate::types::Vec2i;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// One thing a timeline can do. What the names mean (which entity is "bird",
+/// which mode is "play") is up to the game.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Slide `target` from one spot to another
+    Move { target: String, from: Vec2i, to: Vec2i, duration: f64, ease: Ease },
+    /// Start one of `target`'s animations from the beginning
+    Animate { target: String, animation: usize },
+    Sound(String),
+    /// Show some text for a while
+    Text { text: String, position: Vec2i, duration: f64 },
+    /// Stop the clock until the game says there was input
+    WaitInput,
+    Mode(String),
+}
+
+/// What the game has to do this step. Moves are sent every step while they run.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cue {
+    Move { target: String, position: Vec2i },
+    Animate { target: String, animation: usize },
+    Sound(String),
+    Mode(String),
+}
+
+/// A script of actions at times (in seconds) from its start, like an intro or cutscene.
+/// Driven from the game's fixed update step; time doesn't pass while waiting for input.
+///
+/// In a file, every line is a time, an action and its arguments; `#` starts a comment:
+///
+/// ```text
+/// 0.0 move bird -60 300 180 300 1.5 quad_out
+/// 0.0 animate wing 0
+/// 0.2 sound jump
+/// 1.5 text 250 120 2.0 Press Up to fly
+/// 1.5 wait_input
+/// 1.5 mode play
+/// ```
+pub struct Timeline {
+    // sorted by time, actions at the same time stay in file order
+    actions: Vec<(f64, Action)>,
+    next: usize,
+    clock: f64,
+    waiting: bool,
+    moves: Vec<(String, Tween<Vec2i>)>,
+    // text, position, when it goes away
+    texts: Vec<(String, Vec2i, f64)>,
+}
+
+#[allow(dead_code)]
+impl Timeline {
+    pub fn new() -> Self {
+        Self {
+            actions: vec![],
+            next: 0,
+            clock: 0.0,
+            waiting: false,
+            moves: vec![],
+            texts: vec![],
+        }
+    }
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut timeline = Self::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (time, action) = parse_line(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            timeline.at(time, action);
+        }
+        Ok(timeline)
+    }
+    pub fn at(&mut self, time: f64, action: Action) {
+        let index = self.actions.iter().position(|(t, _)| *t > time).unwrap_or(self.actions.len());
+        self.actions.insert(index, (time, action));
+    }
+
+    /// Run the timeline forward by `dt` seconds and return what the game should do, in order.
+    /// `input` is whether whatever the game counts as input for `wait_input` happened this step.
+    pub fn update(&mut self, dt: f64, input: bool) -> Vec<Cue> {
+        let mut cues = vec![];
+        if self.waiting {
+            if !input {
+                return cues;
+            }
+            self.waiting = false;
+        }
+        let target = self.clock + dt;
+        while let Some((time, action)) = self.actions.get(self.next).cloned() {
+            if time > target {
+                break;
+            }
+            // Catch everything up to exactly when this action happens
+            self.run_until(time);
+            self.next += 1;
+            match action {
+                Action::Move { target, from, to, duration, ease } => {
+                    let tween = Tween::new(from, to, Duration::from_secs_f64(duration)).with_ease(ease);
+                    self.moves.retain(|(t, _)| *t != target);
+                    self.moves.push((target, tween));
+                }
+                Action::Animate { target, animation } => cues.push(Cue::Animate { target, animation }),
+                Action::Sound(name) => cues.push(Cue::Sound(name)),
+                Action::Text { text, position, duration } => self.texts.push((text, position, time + duration)),
+                Action::WaitInput => {
+                    self.waiting = true;
+                    break;
+                }
+                Action::Mode(name) => cues.push(Cue::Mode(name)),
+            }
+        }
+        if !self.waiting {
+            self.run_until(target);
+        }
+        for (target, tween) in self.moves.iter() {
+            cues.push(Cue::Move { target: target.clone(), position: tween.value() });
+        }
+        self.moves.retain(|(_, tween)| !tween.finished());
+        cues
+    }
+    /// Text that should be on screen right now, with where to draw it.
+    pub fn texts(&self) -> impl Iterator<Item = (&str, Vec2i)> {
+        self.texts.iter().map(|(text, position, _)| (text.as_str(), *position))
+    }
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+    pub fn finished(&self) -> bool {
+        self.next >= self.actions.len() && !self.waiting && self.moves.is_empty() && self.texts.is_empty()
+    }
+    // Back to the start, ready to play again
+    pub fn reset(&mut self) {
+        self.next = 0;
+        self.clock = 0.0;
+        self.waiting = false;
+        self.moves.clear();
+        self.texts.clear();
+    }
+
+    fn run_until(&mut self, time: f64) {
+        let dt = time - self.clock;
+        for (_, tween) in self.moves.iter_mut() {
+            tween.advance(dt);
+        }
+        self.clock = time;
+        let clock = self.clock;
+        self.texts.retain(|(_, _, until)| *until > clock);
+    }
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_line(line: &str) -> Result<(f64, Action), String> {
+    let mut words = line.split_whitespace();
+    let time = seconds(words.next())?;
+    let name = words.next().ok_or("missing action")?;
+    let action = match name {
+        "move" => {
+            let target = word(words.next())?;
+            let from = Vec2i(number(words.next())? as i32, number(words.next())? as i32);
+            let to = Vec2i(number(words.next())? as i32, number(words.next())? as i32);
+            let duration = seconds(words.next())?;
+            let ease = match words.next() {
+                Some(ease) => parse_ease(ease)?,
+                None => Ease::Linear,
+            };
+            Action::Move { target, from, to, duration, ease }
+        }
+        "animate" => Action::Animate { target: word(words.next())?, animation: number(words.next())? as usize },
+        "sound" => Action::Sound(word(words.next())?),
+        "text" => {
+            let position = Vec2i(number(words.next())? as i32, number(words.next())? as i32);
+            let duration = seconds(words.next())?;
+            // Everything else on the line
+            let text = words.by_ref().collect::<Vec<_>>().join(" ");
+            Action::Text { text, position, duration }
+        }
+        "wait_input" => Action::WaitInput,
+        "mode" => Action::Mode(word(words.next())?),
+        _ => return Err(format!("unknown action {}", name)),
+    };
+    if words.next().is_some() {
+        return Err(format!("too many arguments for {}", name));
+    }
+    Ok((time, action))
+}
+
+fn word(word: Option<&str>) -> Result<String, String> {
+    word.map(|w| w.to_string()).ok_or_else(|| "missing argument".to_string())
+}
+
+fn number(word: Option<&str>) -> Result<f64, String> {
+    let word = word.ok_or("missing argument")?;
+    word.parse().map_err(|_| format!("{} isn't a number", word))
+}
+
+// Times and durations can't go backwards, or the tweens panic once they're playing
+fn seconds(word: Option<&str>) -> Result<f64, String> {
+    let secs = number(word)?;
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(format!("{} isn't a time in seconds", secs));
+    }
+    Ok(secs)
+}
+
+// linear, or a curve and a direction like quad_out or bounce_inout
+pub(crate) fn parse_ease(name: &str) -> Result<Ease, String> {
+    if name == "linear" {
+        return Ok(Ease::Linear);
+    }
+    let mut parts = name.splitn(2, '_');
+    let curve = match parts.next() {
+        Some("quad") => Curve::Quad,
+        Some("cubic") => Curve::Cubic,
+        Some("elastic") => Curve::Elastic,
+        Some("bounce") => Curve::Bounce,
+        Some("back") => Curve::Back,
+        _ => return Err(format!("unknown easing {}", name)),
+    };
+    match parts.next() {
+        Some("in") => Ok(Ease::In(curve)),
+        Some("out") => Ok(Ease::Out(curve)),
+        Some("inout") => Ok(Ease::InOut(curve)),
+        _ => Err(format!("unknown easing {}", name)),
+    }
+}
@@ -0,0 +1,402 @@
+use crate::types::{Rect, Rgba, Vec2i};
+use std::cell::Cell;
+use std::f64::consts::PI;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// The shape of an easing curve, always going from 0 at the start to 1 at the end.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Curve {
+    Quad,
+    Cubic,
+    /// Wobbles like a spring
+    Elastic,
+    /// Hits the end and bounces off it a few times
+    Bounce,
+    /// Pulls back a little before going
+    Back,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Ease {
+    Linear,
+    /// Starts slow
+    In(Curve),
+    /// Ends slow
+    Out(Curve),
+    /// Starts and ends slow
+    InOut(Curve),
+}
+
+impl Curve {
+    // The "in" version; the others are built from this one
+    fn ease_in(self, t: f64) -> f64 {
+        match self {
+            Curve::Quad => t * t,
+            Curve::Cubic => t * t * t,
+            Curve::Elastic => {
+                if t <= 0.0 || t >= 1.0 {
+                    t
+                } else {
+                    -(2.0f64).powf(10.0 * t - 10.0) * ((t * 10.0 - 10.75) * (2.0 * PI / 3.0)).sin()
+                }
+            }
+            Curve::Bounce => 1.0 - bounce_out(1.0 - t),
+            Curve::Back => {
+                let c1 = 1.70158;
+                (c1 + 1.0) * t * t * t - c1 * t * t
+            }
+        }
+    }
+}
+
+fn bounce_out(t: f64) -> f64 {
+    let (n, d) = (7.5625, 2.75);
+    if t < 1.0 / d {
+        n * t * t
+    } else if t < 2.0 / d {
+        let t = t - 1.5 / d;
+        n * t * t + 0.75
+    } else if t < 2.5 / d {
+        let t = t - 2.25 / d;
+        n * t * t + 0.9375
+    } else {
+        let t = t - 2.625 / d;
+        n * t * t + 0.984375
+    }
+}
+
+impl Ease {
+    /// Map progress `t` in 0..=1 to how far along the value should be.
+    /// Elastic and Back go a bit past 0 and 1 on the way.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Ease::Linear => t,
+            Ease::In(curve) => curve.ease_in(t),
+            Ease::Out(curve) => 1.0 - curve.ease_in(1.0 - t),
+            Ease::InOut(curve) => {
+                if t < 0.5 {
+                    curve.ease_in(2.0 * t) / 2.0
+                } else {
+                    1.0 - curve.ease_in(2.0 - 2.0 * t) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// Values that can be blended between two ends.
+/// `t` can go a little outside 0..=1 with the overshooting curves.
+pub trait Lerp: Copy {
+    fn lerp(from: Self, to: Self, t: f64) -> Self;
+}
+
+impl Lerp for f64 {
+    fn lerp(from: Self, to: Self, t: f64) -> Self {
+        from + (to - from) * t
+    }
+}
+
+impl Lerp for f32 {
+    fn lerp(from: Self, to: Self, t: f64) -> Self {
+        from + (to - from) * t as f32
+    }
+}
+
+impl Lerp for i32 {
+    fn lerp(from: Self, to: Self, t: f64) -> Self {
+        f64::lerp(from as f64, to as f64, t).round() as i32
+    }
+}
+
+impl Lerp for Vec2i {
+    fn lerp(from: Self, to: Self, t: f64) -> Self {
+        Vec2i(i32::lerp(from.0, to.0, t), i32::lerp(from.1, to.1, t))
+    }
+}
+
+impl Lerp for Rgba {
+    fn lerp(from: Self, to: Self, t: f64) -> Self {
+        let c = |a: u8, b: u8| f64::lerp(a as f64, b as f64, t).round().clamp(0.0, 255.0) as u8;
+        Rgba(c(from.0, to.0), c(from.1, to.1), c(from.2, to.2), c(from.3, to.3))
+    }
+}
+
+impl Lerp for Rect {
+    fn lerp(from: Self, to: Self, t: f64) -> Self {
+        let size = |a: u16, b: u16| f64::lerp(a as f64, b as f64, t).round().clamp(0.0, u16::MAX as f64) as u16;
+        Rect {
+            x: i32::lerp(from.x, to.x, t),
+            y: i32::lerp(from.y, to.y, t),
+            w: size(from.w, to.w),
+            h: size(from.h, to.h),
+        }
+    }
+}
+
+/// Anything that plays out over time and can go in a `Sequence` or `Parallel`.
+pub trait Tweening {
+    /// Move on by `dt` seconds. Returns whatever part of `dt` was left after finishing,
+    /// so the next step of a sequence can start exactly where this one stopped.
+    fn advance(&mut self, dt: f64) -> f64;
+    fn finished(&self) -> bool;
+    /// Back to the start, ready to play again
+    fn reset(&mut self);
+}
+
+/// Moves a value from `from` to `to` over some time, along an easing curve.
+/// Read the value with `value()`, or `bind` it to a shared cell when the tween is
+/// tucked away inside a sequence or group.
+pub struct Tween<T: Lerp> {
+    from: T,
+    to: T,
+    duration: f64,
+    delay: f64,
+    ease: Ease,
+    yoyo: bool,
+    // seconds since the tween (including its delay) started
+    elapsed: f64,
+    value: T,
+    target: Option<Rc<Cell<T>>>,
+    on_complete: Option<Box<dyn FnMut()>>,
+    done: bool,
+}
+
+#[allow(dead_code)]
+impl<T: Lerp> Tween<T> {
+    pub fn new(from: T, to: T, duration: Duration) -> Self {
+        Self {
+            from,
+            to,
+            duration: duration.as_secs_f64(),
+            delay: 0.0,
+            ease: Ease::Linear,
+            yoyo: false,
+            elapsed: 0.0,
+            value: from,
+            target: None,
+            on_complete: None,
+            done: false,
+        }
+    }
+    pub fn with_ease(mut self, ease: Ease) -> Self {
+        self.ease = ease;
+        self
+    }
+    // Sit at `from` for a while before starting
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay.as_secs_f64();
+        self
+    }
+    // Go to `to` and then back to `from` again, taking twice as long
+    pub fn with_yoyo(mut self) -> Self {
+        self.yoyo = true;
+        self
+    }
+    // Also write the value to `target` every time it changes
+    pub fn bind(mut self, target: &Rc<Cell<T>>) -> Self {
+        target.set(self.value);
+        self.target = Some(Rc::clone(target));
+        self
+    }
+    // Called once, on the step the tween finishes
+    pub fn on_complete(mut self, f: impl FnMut() + 'static) -> Self {
+        self.on_complete = Some(Box::new(f));
+        self
+    }
+    pub fn value(&self) -> T {
+        self.value
+    }
+    // Delay included
+    pub fn total_duration(&self) -> f64 {
+        self.delay + if self.yoyo { 2.0 * self.duration } else { self.duration }
+    }
+
+    fn update_value(&mut self) {
+        let mut t = if self.duration > 0.0 {
+            (self.elapsed - self.delay).max(0.0) / self.duration
+        } else if self.elapsed >= self.delay {
+            // Zero-length tweens jump straight to the end
+            if self.yoyo { 2.0 } else { 1.0 }
+        } else {
+            0.0
+        };
+        if self.yoyo && t > 1.0 {
+            t = 2.0 - t;
+        }
+        self.value = T::lerp(self.from, self.to, self.ease.apply(t.clamp(0.0, 1.0)));
+        if let Some(target) = &self.target {
+            target.set(self.value);
+        }
+    }
+}
+
+impl<T: Lerp> Tweening for Tween<T> {
+    fn advance(&mut self, dt: f64) -> f64 {
+        if self.done {
+            return dt;
+        }
+        self.elapsed += dt;
+        let total = self.total_duration();
+        let left = (self.elapsed - total).max(0.0);
+        self.elapsed = self.elapsed.min(total);
+        self.update_value();
+        if self.elapsed >= total {
+            self.done = true;
+            if let Some(f) = self.on_complete.as_mut() {
+                f();
+            }
+        }
+        left
+    }
+    fn finished(&self) -> bool {
+        self.done
+    }
+    fn reset(&mut self) {
+        self.elapsed = 0.0;
+        self.done = false;
+        self.update_value();
+    }
+}
+
+/// Does nothing for a while; for gaps in a `Sequence`.
+pub struct Delay {
+    duration: f64,
+    elapsed: f64,
+}
+
+impl Delay {
+    pub fn new(duration: Duration) -> Self {
+        Self { duration: duration.as_secs_f64(), elapsed: 0.0 }
+    }
+}
+
+impl Tweening for Delay {
+    fn advance(&mut self, dt: f64) -> f64 {
+        self.elapsed += dt;
+        let left = (self.elapsed - self.duration).max(0.0);
+        self.elapsed = self.elapsed.min(self.duration);
+        left
+    }
+    fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+    fn reset(&mut self) {
+        self.elapsed = 0.0;
+    }
+}
+
+/// Plays its steps one after another.
+pub struct Sequence {
+    steps: Vec<Box<dyn Tweening>>,
+    current: usize,
+    on_complete: Option<Box<dyn FnMut()>>,
+}
+
+#[allow(dead_code)]
+impl Sequence {
+    pub fn new() -> Self {
+        Self { steps: vec![], current: 0, on_complete: None }
+    }
+    pub fn then(mut self, step: impl Tweening + 'static) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+    pub fn wait(self, duration: Duration) -> Self {
+        self.then(Delay::new(duration))
+    }
+    pub fn on_complete(mut self, f: impl FnMut() + 'static) -> Self {
+        self.on_complete = Some(Box::new(f));
+        self
+    }
+}
+
+impl Tweening for Sequence {
+    fn advance(&mut self, mut dt: f64) -> f64 {
+        if self.finished() {
+            return dt;
+        }
+        while self.current < self.steps.len() {
+            dt = self.steps[self.current].advance(dt);
+            if !self.steps[self.current].finished() {
+                return 0.0;
+            }
+            self.current += 1;
+        }
+        if let Some(f) = self.on_complete.as_mut() {
+            f();
+        }
+        dt
+    }
+    fn finished(&self) -> bool {
+        self.current >= self.steps.len()
+    }
+    fn reset(&mut self) {
+        // Reset back to front so bound values end up at the first step's start
+        for step in self.steps.iter_mut().rev() {
+            step.reset();
+        }
+        self.current = 0;
+    }
+}
+
+impl Default for Sequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Plays all of its parts at the same time, and finishes when the longest one does.
+pub struct Parallel {
+    parts: Vec<Box<dyn Tweening>>,
+    on_complete: Option<Box<dyn FnMut()>>,
+    done: bool,
+}
+
+#[allow(dead_code)]
+impl Parallel {
+    pub fn new() -> Self {
+        Self { parts: vec![], on_complete: None, done: false }
+    }
+    pub fn with(mut self, part: impl Tweening + 'static) -> Self {
+        self.parts.push(Box::new(part));
+        self
+    }
+    pub fn on_complete(mut self, f: impl FnMut() + 'static) -> Self {
+        self.on_complete = Some(Box::new(f));
+        self
+    }
+}
+
+impl Tweening for Parallel {
+    fn advance(&mut self, dt: f64) -> f64 {
+        if self.done {
+            return dt;
+        }
+        let left = self.parts.iter_mut().map(|p| p.advance(dt)).fold(dt, f64::min);
+        if self.parts.iter().all(|p| p.finished()) {
+            self.done = true;
+            if let Some(f) = self.on_complete.as_mut() {
+                f();
+            }
+            return left;
+        }
+        0.0
+    }
+    fn finished(&self) -> bool {
+        self.done
+    }
+    fn reset(&mut self) {
+        for part in self.parts.iter_mut() {
+            part.reset();
+        }
+        self.done = false;
+    }
+}
+
+impl Default for Parallel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
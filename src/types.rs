@@ -1,3 +1,5 @@
+// Savefile's derive puts its impls inside a const, which newer compilers warn about
+#![allow(non_local_definitions)]
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Savefile)]
 pub struct Rect {
     pub x: i32,
@@ -18,3 +20,148 @@ pub struct Vec2i(pub i32,pub i32);
 
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, Savefile)]
 pub struct Rgba(pub u8, pub u8, pub u8, pub u8);
+
+/// Pixels with 8 bits after the point, for positions and speeds finer than a whole pixel.
+/// It's all integer math underneath, so it comes out the same on every platform.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash, Debug, Default, Savefile)]
+pub struct Fixed(pub i32);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(1 << 8);
+    pub const fn from_int(n: i32) -> Self {
+        Fixed(n << 8)
+    }
+    // `num / den` pixels, e.g. `Fixed::from_frac(1, 4)` for a quarter
+    pub const fn from_frac(num: i32, den: i32) -> Self {
+        Fixed((num << 8) / den)
+    }
+    // The nearest one to `f`
+    pub fn from_f32(f: f32) -> Self {
+        Fixed((f * 256.0).round() as i32)
+    }
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / 256.0
+    }
+    // Whole pixels, rounding down even for negative numbers
+    pub fn floor(self) -> i32 {
+        self.0 >> 8
+    }
+    pub fn round(self) -> i32 {
+        (self.0 + 128) >> 8
+    }
+    // What's left after `floor`, from 0 up to just under a pixel
+    pub fn fract(self) -> Fixed {
+        Fixed(self.0 & 0xff)
+    }
+    pub fn abs(self) -> Fixed {
+        Fixed(self.0.abs())
+    }
+    // Rounded down; negative numbers have no square root, so they give zero
+    pub fn sqrt(self) -> Fixed {
+        // sqrt(n / 256) * 256 is sqrt(n * 256)
+        let n = (self.0.max(0) as u64) << 8;
+        let mut root = n;
+        let mut next = n.div_ceil(2);
+        while next < root {
+            root = next;
+            next = (root + n / root) / 2;
+        }
+        Fixed(root as i32)
+    }
+}
+
+impl From<i32> for Fixed {
+    fn from(n: i32) -> Self {
+        Fixed::from_int(n)
+    }
+}
+
+impl std::ops::Add for Fixed {
+    type Output = Fixed;
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0 + other.0)
+    }
+}
+
+impl std::ops::Sub for Fixed {
+    type Output = Fixed;
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0 - other.0)
+    }
+}
+
+impl std::ops::Neg for Fixed {
+    type Output = Fixed;
+    fn neg(self) -> Fixed {
+        Fixed(-self.0)
+    }
+}
+
+impl std::ops::Mul for Fixed {
+    type Output = Fixed;
+    fn mul(self, other: Fixed) -> Fixed {
+        Fixed(((self.0 as i64 * other.0 as i64) >> 8) as i32)
+    }
+}
+
+impl std::ops::Div for Fixed {
+    type Output = Fixed;
+    fn div(self, other: Fixed) -> Fixed {
+        Fixed((((self.0 as i64) << 8) / other.0 as i64) as i32)
+    }
+}
+
+impl std::ops::Mul<i32> for Fixed {
+    type Output = Fixed;
+    fn mul(self, n: i32) -> Fixed {
+        Fixed(self.0 * n)
+    }
+}
+
+impl std::ops::AddAssign for Fixed {
+    fn add_assign(&mut self, other: Fixed) {
+        self.0 += other.0;
+    }
+}
+
+impl std::ops::SubAssign for Fixed {
+    fn sub_assign(&mut self, other: Fixed) {
+        self.0 -= other.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_floor_and_fract_on_negatives() {
+        // -1.5 is -2 and a half
+        let x = Fixed::from_frac(-3, 2);
+        assert_eq!(x.floor(), -2);
+        assert_eq!(x.fract(), Fixed::from_frac(1, 2));
+        let x = Fixed::from_f32(-0.25);
+        assert_eq!(x.floor(), -1);
+        assert_eq!(x.fract(), Fixed::from_frac(3, 4));
+        // Whole numbers have nothing left over
+        assert_eq!(Fixed::from_int(-3).floor(), -3);
+        assert_eq!(Fixed::from_int(-3).fract(), Fixed::ZERO);
+        // Putting them back together always gives the same number
+        for n in -600..600 {
+            let x = Fixed(n);
+            assert_eq!(Fixed::from_int(x.floor()) + x.fract(), x);
+            assert!(x.fract() >= Fixed::ZERO && x.fract() < Fixed::ONE);
+        }
+    }
+
+    #[test]
+    fn fixed_div_and_sqrt() {
+        assert_eq!(Fixed::from_int(3) / Fixed::from_int(4), Fixed::from_frac(3, 4));
+        assert_eq!(Fixed::from_int(-1) / Fixed::from_int(2), Fixed::from_frac(-1, 2));
+        assert_eq!(Fixed::from_int(9).sqrt(), Fixed::from_int(3));
+        assert_eq!(Fixed::from_frac(1, 4).sqrt(), Fixed::from_frac(1, 2));
+        assert_eq!(Fixed::from_int(2).sqrt(), Fixed(362));
+        assert_eq!(Fixed::from_int(-4).sqrt(), Fixed::ZERO);
+    }
+}